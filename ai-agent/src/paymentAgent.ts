@@ -131,6 +131,7 @@ export class PaymentAgent {
       
       const result = await this.stellarClient.executeRefund(
         mockInvoiceId,
+        command.amount,
         reason,
         userPublicKey
       );
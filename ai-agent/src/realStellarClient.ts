@@ -98,7 +98,8 @@ export class RealStellarClient {
         amount: command.amount,
         currency: command.currency,
         recipient: command.recipient,
-        description: command.description || ''
+        description: command.description || '',
+        expiry: null
       }
     });
   }
@@ -124,7 +125,7 @@ export class RealStellarClient {
     }
   }
 
-  async executeRefund(invoiceId: string, reason: string, refunderPublicKey: string): Promise<{
+  async executeRefund(invoiceId: string, refundAmount: number, reason: string, refunderPublicKey: string): Promise<{
     transactionXDR: string;
   }> {
     try {
@@ -133,7 +134,9 @@ export class RealStellarClient {
         method: 'execute_refund',
         params: {
           invoice_id: invoiceId,
-          reason: reason
+          refund_amount: refundAmount,
+          reason: reason,
+          initiator: refunderPublicKey
         }
       });
       
@@ -156,7 +159,10 @@ export class RealStellarClient {
           currency: command.currency,
           recipient: command.recipient,
           interval_days: command.schedule?.interval_days || 30,
-          payer: payerPublicKey
+          payer: payerPublicKey,
+          allowance_periods: 12,
+          max_payments: null,
+          end_at: null
         }
       });
       
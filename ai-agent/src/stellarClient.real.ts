@@ -53,7 +53,8 @@ export class StellarClient {
             nativeToScVal(amountInStroops, { type: 'i128' }),
             nativeToScVal(command.currency, { type: 'address' }), // Token contract address
             nativeToScVal(command.recipient, { type: 'address' }),
-            nativeToScVal(command.description || '', { type: 'string' })
+            nativeToScVal(command.description || '', { type: 'string' }),
+            nativeToScVal(null) // No expiry
           )
         )
         .setTimeout(30)
@@ -141,6 +142,7 @@ export class StellarClient {
    */
   async executeRefund(
     invoiceId: string, 
+    refundAmount: number,
     reason: string, 
     refunderKeypair: any
   ): Promise<StellarTransaction> {
@@ -156,7 +158,9 @@ export class StellarClient {
           contract.call(
             'execute_refund',
             nativeToScVal(parseInt(invoiceId), { type: 'u64' }),
-            nativeToScVal(reason, { type: 'string' })
+            nativeToScVal(Math.floor(refundAmount * 10000000), { type: 'i128' }),
+            nativeToScVal(reason, { type: 'string' }),
+            nativeToScVal(refunderKeypair.publicKey(), { type: 'address' })
           )
         )
         .setTimeout(30)
@@ -214,7 +218,10 @@ export class StellarClient {
             nativeToScVal(command.currency, { type: 'address' }),
             nativeToScVal(command.recipient, { type: 'address' }),
             nativeToScVal(intervalDays, { type: 'u32' }),
-            nativeToScVal(payerKeypair.publicKey(), { type: 'address' })
+            nativeToScVal(payerKeypair.publicKey(), { type: 'address' }),
+            nativeToScVal(12, { type: 'u32' }), // Pre-approve 12 payments
+            nativeToScVal(null), // No max_payments
+            nativeToScVal(null) // No end_at
           )
         )
         .setTimeout(30)
@@ -382,6 +389,8 @@ export class StellarClient {
 
       const amountInStroops = Math.floor(amount * 10000000);
       const minAmountOutStroops = Math.floor(minAmountOut * 10000000);
+      // The swap must land within five minutes or it fails instead of filling late
+      const deadline = Math.floor(Date.now() / 1000) + 300;
 
       const transaction = new TransactionBuilder(account, {
         fee: '100000',
@@ -394,8 +403,11 @@ export class StellarClient {
             nativeToScVal(toAsset, { type: 'address' }),
             nativeToScVal(amountInStroops, { type: 'i128' }),
             nativeToScVal(minAmountOutStroops, { type: 'i128' }),
+            nativeToScVal(0, { type: 'i128' }), // No quote, minAmountOut bounds the output
+            nativeToScVal(null), // Default slippage tolerance
             nativeToScVal(payerKeypair.publicKey(), { type: 'address' }),
-            nativeToScVal(payerKeypair.publicKey(), { type: 'address' })
+            nativeToScVal(payerKeypair.publicKey(), { type: 'address' }),
+            nativeToScVal(deadline, { type: 'u64' })
           )
         )
         .setTimeout(30)
@@ -0,0 +1,84 @@
+use soroban_sdk::{contractevent, Address};
+
+#[contractevent(topics = ["pool_created"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PoolCreated {
+    pub token_a: Address,
+    pub token_b: Address,
+    pub share_token: Address,
+}
+
+#[contractevent(topics = ["liquidity_added"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidityAdded {
+    pub provider: Address,
+    pub token_a: Address,
+    pub token_b: Address,
+    pub amount_a: i128,
+    pub amount_b: i128,
+    pub shares: i128,
+}
+
+#[contractevent(topics = ["liquidity_removed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidityRemoved {
+    pub provider: Address,
+    pub token_a: Address,
+    pub token_b: Address,
+    pub amount_a: i128,
+    pub amount_b: i128,
+    pub shares: i128,
+}
+
+#[contractevent(topics = ["swap"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Swap {
+    pub from_asset: Address,
+    pub to_asset: Address,
+    pub amount_in: i128,
+    pub amount_out: i128,
+    pub fees_paid: i128,
+}
+
+#[contractevent(topics = ["asset_supported"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AssetSupported {
+    pub asset: Address,
+}
+
+#[contractevent(topics = ["asset_unsupported"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AssetUnsupported {
+    pub asset: Address,
+}
+
+#[contractevent(topics = ["pair_oracle_set"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PairOracleSet {
+    pub token_a: Address,
+    pub token_b: Address,
+    pub oracle: Address,
+    pub max_deviation_bps: i128,
+    pub max_age_seconds: u64,
+}
+
+#[contractevent(topics = ["pair_oracle_removed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PairOracleRemoved {
+    pub token_a: Address,
+    pub token_b: Address,
+}
+
+#[contractevent(topics = ["protocol_fee_share_set"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProtocolFeeShareSet {
+    pub share_bps: i128,
+}
+
+#[contractevent(topics = ["protocol_fees_collected"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProtocolFeesCollected {
+    pub asset: Address,
+    pub to: Address,
+    pub amount: i128,
+}
@@ -0,0 +1,86 @@
+use admin_sep::AdministratableExtension;
+use soroban_sdk::{contractimpl, token, Address, Env};
+
+use crate::{
+    events,
+    pool::{BPS, MAX_BUMP},
+    DataKey, Error, MultiAssetRouter, MultiAssetRouterArgs, MultiAssetRouterClient,
+};
+
+#[contractimpl]
+impl MultiAssetRouter {
+    /// Get the share of every swap's LP fee kept by the protocol, in basis points
+    /// of the fee
+    pub fn get_protocol_fee_share(env: Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::ProtocolFeeShare)
+            .unwrap_or(0)
+    }
+
+    /// Set the share of every swap's LP fee kept by the protocol, in basis points
+    /// of the fee (admin only). Zero turns the protocol fee off.
+    pub fn set_protocol_fee_share(env: Env, share_bps: i128) -> Result<(), Error> {
+        Self::require_admin(&env);
+
+        if !(0..=BPS).contains(&share_bps) {
+            return Err(Error::InvalidAmount);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ProtocolFeeShare, &share_bps);
+
+        events::ProtocolFeeShareSet { share_bps }.publish(&env);
+        Ok(())
+    }
+
+    /// Get the protocol fees accrued in `asset` and not yet collected
+    pub fn get_protocol_fees(env: Env, asset: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&DataKey::ProtocolFees(asset))
+            .unwrap_or(0)
+    }
+
+    /// Send the protocol fees accrued in `asset` to `to` (admin only).
+    /// Returns the amount collected.
+    pub fn collect_protocol_fees(env: Env, asset: Address, to: Address) -> i128 {
+        Self::require_admin(&env);
+
+        let amount = Self::get_protocol_fees(env.clone(), asset.clone());
+        if amount == 0 {
+            return 0;
+        }
+
+        env.storage()
+            .persistent()
+            .remove(&DataKey::ProtocolFees(asset.clone()));
+        token::Client::new(&env, &asset).transfer(&env.current_contract_address(), &to, &amount);
+
+        events::ProtocolFeesCollected { asset, to, amount }.publish(&env);
+        amount
+    }
+}
+
+impl MultiAssetRouter {
+    /// Set aside the protocol's share of an LP fee of `fee` in `asset`,
+    /// returning the amount set aside. It is held by the router outside any
+    /// pool's reserves until collected.
+    pub(crate) fn accrue_protocol_fee(env: &Env, asset: &Address, fee: i128) -> i128 {
+        let protocol_fee = fee * Self::get_protocol_fee_share(env.clone()) / BPS;
+        if protocol_fee == 0 {
+            return 0;
+        }
+
+        let key = DataKey::ProtocolFees(asset.clone());
+        let accrued = Self::get_protocol_fees(env.clone(), asset.clone());
+        env.storage()
+            .persistent()
+            .set(&key, &(accrued + protocol_fee));
+        env.storage()
+            .persistent()
+            .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+        protocol_fee
+    }
+}
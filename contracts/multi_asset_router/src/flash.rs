@@ -0,0 +1,76 @@
+use soroban_sdk::{contractclient, contractimpl, token, vec, Address, Bytes, Env};
+
+use crate::{Error, MultiAssetRouter, MultiAssetRouterArgs, MultiAssetRouterClient};
+
+/// Contract a flash swap's output is lent to
+#[contractclient(name = "FlashSwapReceiverClient")]
+pub trait FlashSwapReceiver {
+    /// Called once `amount_out` of `token_out` has been sent to the receiver.
+    /// Before returning, the receiver must transfer at least `amount_owed` of
+    /// `token_in` to the router.
+    fn on_flash_swap(
+        env: Env,
+        initiator: Address,
+        token_out: Address,
+        amount_out: i128,
+        token_in: Address,
+        amount_owed: i128,
+        data: Bytes,
+    );
+}
+
+#[contractimpl]
+impl MultiAssetRouter {
+    /// Send `amount_out` of `token_out` from the pool with `token_in` to `receiver`
+    /// and call its `on_flash_swap`, which must pay the pool back in `token_in`.
+    ///
+    /// The receiver owes what an exact-output swap of `amount_out` would cost,
+    /// LP fee included, and the swap is then settled like any other. Soroban
+    /// doesn't let the receiver call back into the router, so the pool can't be
+    /// swapped through or have its liquidity changed while it runs. Fails with
+    /// `FlashSwapNotRepaid` if the receiver pays back less than it owes.
+    /// Returns the amount owed.
+    pub fn flash_swap(
+        env: Env,
+        initiator: Address,
+        receiver: Address,
+        token_in: Address,
+        token_out: Address,
+        amount_out: i128,
+        data: Bytes,
+    ) -> Result<i128, Error> {
+        initiator.require_auth();
+        Self::require_supported(&env, &token_in)?;
+        Self::require_supported(&env, &token_out)?;
+
+        let pool = Self::get_pool(env.clone(), token_in.clone(), token_out.clone())?;
+        let amount_owed = pool.quote_exact_out(&token_in, amount_out)?;
+
+        let contract_address = env.current_contract_address();
+        let token_in_client = token::Client::new(&env, &token_in);
+        let balance_before = token_in_client.balance(&contract_address);
+
+        token::Client::new(&env, &token_out).transfer(&contract_address, &receiver, &amount_out);
+        FlashSwapReceiverClient::new(&env, &receiver).on_flash_swap(
+            &initiator,
+            &token_out,
+            &amount_out,
+            &token_in,
+            &amount_owed,
+            &data,
+        );
+
+        // Anything paid over what is owed stays in the pool
+        let repaid = token_in_client.balance(&contract_address) - balance_before;
+        if repaid < amount_owed {
+            return Err(Error::FlashSwapNotRepaid);
+        }
+        Self::swap_hops(
+            &env,
+            &vec![&env, token_in, token_out],
+            &vec![&env, repaid, amount_out],
+        )?;
+
+        Ok(amount_owed)
+    }
+}
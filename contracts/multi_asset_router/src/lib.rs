@@ -1,10 +1,22 @@
 #![no_std]
 use admin_sep::{Administratable, Upgradable, AdministratableExtension};
 use soroban_sdk::{
-    contract, contractimpl, contracttype, contracterror, Address, Env, Vec,
-    token, log
+    contract, contractimpl, contracttype, contracterror, Address, Env, Vec
 };
 
+pub mod events;
+pub mod fees;
+pub mod flash;
+pub mod oracle;
+pub mod path;
+pub mod pool;
+pub mod swap;
+pub mod twap;
+
+pub use oracle::OracleConfig;
+pub use pool::Pool;
+pub use twap::Observation;
+
 #[contract]
 pub struct MultiAssetRouter;
 
@@ -18,6 +30,19 @@ pub struct SwapPath {
     pub path: Vec<Address>, // Intermediate assets for multi-hop swaps
 }
 
+/// One swap of a batch, paid for by the batch's payer
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwapLeg {
+    pub from_asset: Address,
+    pub to_asset: Address,
+    pub recipient: Address,
+    pub amount_in: i128,
+    pub min_amount_out: i128,
+    pub quoted_amount_out: i128, // Output the caller was quoted for the leg
+    pub slippage_bps: Option<i128>, // Allowed below the quote; default slippage tolerance if None
+}
+
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SwapResult {
@@ -26,11 +51,27 @@ pub struct SwapResult {
     pub fees_paid: i128,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwapExactOutResult {
+    pub amount_in: i128,
+    pub price_impact: i128, // Basis points (100 = 1%)
+    pub fees_paid: i128, // In units of the input asset
+}
+
 #[contracttype]
 pub enum DataKey {
     SlippageTolerance,
+    MaxSlippage,
     MaxHops,
     SupportedAssets,
+    Pool(Address, Address),
+    ShareTokenWasm,
+    ProtocolFeeShare,
+    ProtocolFees(Address),
+    Observations(Address, Address),
+    PoolAssets(Address),
+    PairOracle(Address, Address),
 }
 
 #[contracterror]
@@ -43,6 +84,17 @@ pub enum Error {
     InvalidPath = 4,
     SwapFailed = 5,
     UnauthorizedAsset = 6,
+    PoolNotFound = 7,
+    PoolExists = 8,
+    InvalidAmount = 9,
+    InsufficientShares = 10,
+    InvalidSlippage = 11,
+    DeadlinePassed = 12,
+    PriceDeviation = 13,
+    OraclePriceUnavailable = 14,
+    ShareTokenNotSet = 15,
+    FlashSwapNotRepaid = 16,
+    TwapUnavailable = 17,
 }
 
 #[contractimpl]
@@ -57,73 +109,29 @@ impl MultiAssetRouter {
         Self::set_admin(env, admin);
         // Default 1% slippage tolerance (100 basis points)
         env.storage().instance().set(&DataKey::SlippageTolerance, &100i128);
+        // Callers may accept up to 10% slippage
+        env.storage().instance().set(&DataKey::MaxSlippage, &1000i128);
         // Maximum 3 hops for swap paths
         env.storage().instance().set(&DataKey::MaxHops, &3u32);
     }
 
-    /// Execute a token swap with automatic path finding
-    pub fn execute_swap(
-        env: Env,
-        from_asset: Address,
-        to_asset: Address,
-        amount_in: i128,
-        min_amount_out: i128,
-        payer: Address,
-        recipient: Address,
-    ) -> Result<SwapResult, Error> {
-        payer.require_auth();
-
-        if amount_in <= 0 {
-            return Err(Error::InvalidAsset);
-        }
-
-        // If same asset, just transfer
-        if from_asset == to_asset {
-            let token_client = token::Client::new(&env, &from_asset);
-            token_client.transfer(&payer, &recipient, &amount_in);
-            
-            return Ok(SwapResult {
-                amount_out: amount_in,
-                price_impact: 0,
-                fees_paid: 0,
-            });
-        }
-
-        // Find optimal swap path
-        let swap_path = Self::find_optimal_path(&env, from_asset.clone(), to_asset.clone(), amount_in)?;
-        
-        // Execute the swap
-        let result = Self::execute_swap_path(&env, swap_path, payer, recipient)?;
-        
-        // Check slippage
-        if result.amount_out < min_amount_out {
-            return Err(Error::SlippageExceeded);
-        }
-
-        log!(&env, "Swap executed: {} -> {}, amount: {}", from_asset, to_asset, result.amount_out);
-        
-        Ok(result)
-    }
-
-    /// Find the optimal swap path between two assets
+    /// Find the optimal swap path between two assets.
+    ///
+    /// `min_amount_out` allows for `slippage_bps`, or the default slippage
+    /// tolerance if `None`. Fails with `InvalidSlippage` above the maximum slippage.
     pub fn find_optimal_path(
         env: &Env,
         from_asset: Address,
         to_asset: Address,
         amount_in: i128,
+        slippage_bps: Option<i128>,
     ) -> Result<SwapPath, Error> {
-        // For now, implement direct swap path
-        // In a full implementation, this would query Stellar DEX for liquidity
-        // and find the best multi-hop path
-        
-        let path = Vec::new(&env);
-        
-        // Simulate price calculation (in real implementation, query Stellar DEX)
-        let estimated_out = Self::estimate_swap_output(&env, from_asset.clone(), to_asset.clone(), amount_in)?;
+        let slippage_tolerance = Self::slippage_tolerance(env, slippage_bps)?;
+
+        // Search pools up to MaxHops deep for the route with the best output
+        let path = Self::best_path(env, &from_asset, &to_asset, amount_in)?;
         
-        let slippage_tolerance: i128 = env.storage().instance()
-            .get(&DataKey::SlippageTolerance)
-            .unwrap_or(100);
+        let estimated_out = Self::quote_path(env, &from_asset, &path, &to_asset, amount_in)?.amount_out;
         
         let min_amount_out = estimated_out * (10000 - slippage_tolerance) / 10000;
         
@@ -136,69 +144,14 @@ impl MultiAssetRouter {
         })
     }
 
-    /// Estimate swap output amount
+    /// Estimate swap output amount along the best path, after the LP fees
     pub fn estimate_swap_output(
-        _env: &Env,
-        _from_asset: Address,
-        _to_asset: Address,
+        env: &Env,
+        from_asset: Address,
+        to_asset: Address,
         amount_in: i128,
     ) -> Result<i128, Error> {
-        // Simplified price estimation
-        // In real implementation, this would query Stellar DEX orderbook
-        
-        // Mock exchange rate (1:1 for demo, real implementation would fetch from DEX)
-        let exchange_rate = 1000000i128; // 1.0 in 6 decimal places
-        let estimated_out = amount_in * exchange_rate / 1000000;
-        
-        // Simulate 0.3% trading fee
-        let fees = estimated_out * 30 / 10000;
-        let amount_out = estimated_out - fees;
-        
-        if amount_out <= 0 {
-            return Err(Error::InsufficientLiquidity);
-        }
-        
-        Ok(amount_out)
-    }
-
-    /// Execute a swap along a specific path
-    fn execute_swap_path(
-        env: &Env,
-        swap_path: SwapPath,
-        payer: Address,
-        recipient: Address,
-    ) -> Result<SwapResult, Error> {
-        let from_token = token::Client::new(&env, &swap_path.from_asset);
-        let to_token = token::Client::new(&env, &swap_path.to_asset);
-        
-        // Transfer input tokens from payer to contract
-        let contract_address = env.current_contract_address();
-        from_token.transfer(&payer, &contract_address, &swap_path.amount_in);
-        
-        // In a real implementation, this would interact with Stellar DEX
-        // For now, simulate the swap
-        let estimated_out = Self::estimate_swap_output(
-            &env, 
-            swap_path.from_asset.clone(), 
-            swap_path.to_asset.clone(), 
-            swap_path.amount_in
-        )?;
-        
-        // Simulate fees (0.3%)
-        let fees_paid = estimated_out * 30 / 10000;
-        let amount_out = estimated_out - fees_paid;
-        
-        // In real implementation, execute DEX trade here
-        // For demo, we'll simulate having the output tokens
-        
-        // Transfer output tokens to recipient
-        to_token.transfer(&contract_address, &recipient, &amount_out);
-        
-        Ok(SwapResult {
-            amount_out,
-            price_impact: 30, // 0.3% in basis points
-            fees_paid,
-        })
+        Ok(Self::get_swap_quote(env.clone(), from_asset, to_asset, amount_in)?.amount_out)
     }
 
     /// Get current slippage tolerance
@@ -208,45 +161,104 @@ impl MultiAssetRouter {
             .unwrap_or(100)
     }
 
-    /// Set slippage tolerance (admin only)
+    /// Set slippage tolerance (admin only). It can't exceed the maximum slippage.
     pub fn set_slippage_tolerance(env: Env, tolerance_bps: i128) -> Result<(), Error> {
         Self::require_admin(&env);
         
-        if tolerance_bps < 0 || tolerance_bps > 1000 { // Max 10%
-            return Err(Error::InvalidAsset);
+        if !(0..=Self::get_max_slippage(env.clone())).contains(&tolerance_bps) {
+            return Err(Error::InvalidSlippage);
         }
         
         env.storage().instance().set(&DataKey::SlippageTolerance, &tolerance_bps);
         Ok(())
     }
 
-    /// Add supported asset (admin only)
+    /// Get the maximum slippage callers may pass to `find_optimal_path`
+    pub fn get_max_slippage(env: Env) -> i128 {
+        env.storage().instance()
+            .get(&DataKey::MaxSlippage)
+            .unwrap_or(1000)
+    }
+
+    /// Set the maximum slippage callers may pass (admin only). It can't be set
+    /// below the default slippage tolerance.
+    pub fn set_max_slippage(env: Env, max_slippage_bps: i128) -> Result<(), Error> {
+        Self::require_admin(&env);
+
+        let tolerance_bps = Self::get_slippage_tolerance(env.clone());
+        if !(tolerance_bps..=pool::BPS).contains(&max_slippage_bps) {
+            return Err(Error::InvalidSlippage);
+        }
+
+        env.storage().instance().set(&DataKey::MaxSlippage, &max_slippage_bps);
+        Ok(())
+    }
+
+    /// Get the maximum number of pools a swap path may go through
+    pub fn get_max_hops(env: Env) -> u32 {
+        env.storage().instance()
+            .get(&DataKey::MaxHops)
+            .unwrap_or(3)
+    }
+
+    /// Set the maximum number of pools a swap path may go through (admin only)
+    pub fn set_max_hops(env: Env, max_hops: u32) -> Result<(), Error> {
+        Self::require_admin(&env);
+
+        if !(1..=path::MAX_HOPS_LIMIT).contains(&max_hops) {
+            return Err(Error::InvalidPath);
+        }
+
+        env.storage().instance().set(&DataKey::MaxHops, &max_hops);
+        Ok(())
+    }
+
+    /// Add supported asset (admin only). Only supported assets can be swapped,
+    /// quoted or routed through.
     pub fn add_supported_asset(env: Env, asset: Address) -> Result<(), Error> {
         Self::require_admin(&env);
         
-        let mut supported_assets: Vec<Address> = env.storage().instance()
-            .get(&DataKey::SupportedAssets)
-            .unwrap_or(Vec::new(&env));
+        let mut supported_assets = Self::list_supported_assets(env.clone());
+        if supported_assets.contains(&asset) {
+            return Ok(());
+        }
         
-        supported_assets.push_back(asset);
+        supported_assets.push_back(asset.clone());
         env.storage().instance().set(&DataKey::SupportedAssets, &supported_assets);
+
+        events::AssetSupported { asset }.publish(&env);
         
         Ok(())
     }
 
+    /// Remove supported asset (admin only). Swaps and quotes in or through the
+    /// asset fail from then on, but its liquidity can still be withdrawn.
+    pub fn remove_supported_asset(env: Env, asset: Address) -> Result<(), Error> {
+        Self::require_admin(&env);
+
+        let mut supported_assets = Self::list_supported_assets(env.clone());
+        let Some(index) = supported_assets.first_index_of(&asset) else {
+            return Ok(());
+        };
+
+        supported_assets.remove(index);
+        env.storage().instance().set(&DataKey::SupportedAssets, &supported_assets);
+
+        events::AssetUnsupported { asset }.publish(&env);
+
+        Ok(())
+    }
+
+    /// List the supported assets
+    pub fn list_supported_assets(env: Env) -> Vec<Address> {
+        env.storage().instance()
+            .get(&DataKey::SupportedAssets)
+            .unwrap_or(Vec::new(&env))
+    }
+
     /// Check if asset is supported
     pub fn is_asset_supported(env: Env, asset: Address) -> bool {
-        let supported_assets: Vec<Address> = env.storage().instance()
-            .get(&DataKey::SupportedAssets)
-            .unwrap_or(Vec::new(&env));
-        
-        for supported_asset in supported_assets.iter() {
-            if supported_asset == asset {
-                return true;
-            }
-        }
-        
-        false
+        Self::list_supported_assets(env).contains(&asset)
     }
 
     /// Get quote for a potential swap
@@ -256,14 +268,87 @@ impl MultiAssetRouter {
         to_asset: Address,
         amount_in: i128,
     ) -> Result<SwapResult, Error> {
-        let estimated_out = Self::estimate_swap_output(&env, from_asset, to_asset, amount_in)?;
-        let fees_paid = estimated_out * 30 / 10000;
-        let amount_out = estimated_out - fees_paid;
-        
-        Ok(SwapResult {
-            amount_out,
-            price_impact: 30,
-            fees_paid,
+        // Same asset swaps are plain transfers
+        if from_asset == to_asset {
+            Self::require_supported(&env, &from_asset)?;
+            if amount_in <= 0 {
+                return Err(Error::InvalidAmount);
+            }
+            return Ok(SwapResult {
+                amount_out: amount_in,
+                price_impact: 0,
+                fees_paid: 0,
+            });
+        }
+
+        let path = Self::best_path(&env, &from_asset, &to_asset, amount_in)?;
+        Self::quote_path(&env, &from_asset, &path, &to_asset, amount_in)
+    }
+
+    /// Get quote for the input a swap to exactly `amount_out` would need
+    pub fn get_swap_quote_exact_out(
+        env: Env,
+        from_asset: Address,
+        to_asset: Address,
+        amount_out: i128,
+    ) -> Result<SwapExactOutResult, Error> {
+        // Same asset swaps are plain transfers
+        if from_asset == to_asset {
+            Self::require_supported(&env, &from_asset)?;
+            if amount_out <= 0 {
+                return Err(Error::InvalidAmount);
+            }
+            return Ok(SwapExactOutResult {
+                amount_in: amount_out,
+                price_impact: 0,
+                fees_paid: 0,
+            });
+        }
+
+        let path = Self::best_path_exact_out(&env, &from_asset, &to_asset, amount_out)?;
+        let assets = Self::hops(&env, &from_asset, &path, &to_asset);
+        let amounts = Self::amounts_exact_out(&env, &assets, amount_out)?;
+        let result = Self::price_hops(&env, &assets, &amounts)?;
+        Ok(SwapExactOutResult {
+            amount_in: amounts.first_unchecked(),
+            price_impact: result.price_impact,
+            fees_paid: result.fees_paid,
         })
     }
-}
\ No newline at end of file
+}
+
+impl MultiAssetRouter {
+    /// The slippage a caller allows, in basis points: `slippage_bps`, or the
+    /// default tolerance if `None`. Fails with `InvalidSlippage` above the
+    /// maximum slippage.
+    fn slippage_tolerance(env: &Env, slippage_bps: Option<i128>) -> Result<i128, Error> {
+        match slippage_bps {
+            Some(slippage_bps) => {
+                if !(0..=Self::get_max_slippage(env.clone())).contains(&slippage_bps) {
+                    return Err(Error::InvalidSlippage);
+                }
+                Ok(slippage_bps)
+            }
+            None => Ok(Self::get_slippage_tolerance(env.clone())),
+        }
+    }
+
+    /// Fail with `DeadlinePassed` once the ledger timestamp is past `deadline`
+    fn check_deadline(env: &Env, deadline: u64) -> Result<(), Error> {
+        if env.ledger().timestamp() > deadline {
+            return Err(Error::DeadlinePassed);
+        }
+        Ok(())
+    }
+
+    /// Fail with `UnauthorizedAsset` unless `asset` is on the allowlist
+    fn require_supported(env: &Env, asset: &Address) -> Result<(), Error> {
+        if !Self::is_asset_supported(env.clone(), asset.clone()) {
+            return Err(Error::UnauthorizedAsset);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,148 @@
+use admin_sep::AdministratableExtension;
+use soroban_sdk::{contractclient, contractimpl, contracttype, Address, Env, Symbol};
+
+use crate::{
+    events,
+    pool::{sort_pair, BPS, MAX_BUMP},
+    DataKey, Error, MultiAssetRouter, MultiAssetRouterArgs, MultiAssetRouterClient,
+};
+
+/// Asset identifier of the SEP-40 price feed interface
+#[contracttype(export = false)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+/// Price record of the SEP-40 price feed interface
+#[contracttype(export = false)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+/// The subset of the SEP-40 price feed interface used to sanity check swaps.
+/// Both assets of a pair must be priced in the same base asset.
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn lastprice(env: Env, asset: Asset) -> Option<PriceData>;
+}
+
+/// Oracle registered for a pool's token pair
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OracleConfig {
+    pub oracle: Address,
+    /// How far a swap's execution price may be from the oracle's, in basis points
+    pub max_deviation_bps: i128,
+    /// How old, in seconds, an oracle price may be for a swap to be checked against it
+    pub max_age_seconds: u64,
+}
+
+#[contractimpl]
+impl MultiAssetRouter {
+    /// Register the price oracle swaps between `token_a` and `token_b` are checked
+    /// against, replacing any previous one (admin only). Prices older than
+    /// `max_age_seconds` are treated as unavailable.
+    pub fn set_pair_oracle(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+        oracle: Address,
+        max_deviation_bps: i128,
+        max_age_seconds: u64,
+    ) -> Result<(), Error> {
+        Self::require_admin(&env);
+
+        if !(0..=BPS).contains(&max_deviation_bps) || max_age_seconds == 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let (token_a, token_b) = sort_pair(token_a, token_b);
+        let key = DataKey::PairOracle(token_a.clone(), token_b.clone());
+        let config = OracleConfig {
+            oracle: oracle.clone(),
+            max_deviation_bps,
+            max_age_seconds,
+        };
+        env.storage().persistent().set(&key, &config);
+        env.storage()
+            .persistent()
+            .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+
+        events::PairOracleSet {
+            token_a,
+            token_b,
+            oracle,
+            max_deviation_bps,
+            max_age_seconds,
+        }
+        .publish(&env);
+        Ok(())
+    }
+
+    /// Stop checking swaps between `token_a` and `token_b` against an oracle (admin only)
+    pub fn remove_pair_oracle(env: Env, token_a: Address, token_b: Address) {
+        Self::require_admin(&env);
+
+        let (token_a, token_b) = sort_pair(token_a, token_b);
+        env.storage()
+            .persistent()
+            .remove(&DataKey::PairOracle(token_a.clone(), token_b.clone()));
+
+        events::PairOracleRemoved { token_a, token_b }.publish(&env);
+    }
+
+    /// Get the oracle registered for a token pair, in either order
+    pub fn get_pair_oracle(env: Env, token_a: Address, token_b: Address) -> Option<OracleConfig> {
+        let (token_a, token_b) = sort_pair(token_a, token_b);
+        env.storage()
+            .persistent()
+            .get(&DataKey::PairOracle(token_a, token_b))
+    }
+}
+
+impl MultiAssetRouter {
+    /// Check a swap of `amount_in` of `from_asset` for `amount_out` of `to_asset`
+    /// is priced within the deviation allowed by the pair's oracle, if it has one.
+    ///
+    /// Fails with `PriceDeviation` if it isn't, or `OraclePriceUnavailable` if
+    /// the oracle has no price for either asset newer than the pair's maximum age.
+    pub(crate) fn check_oracle_price(
+        env: &Env,
+        from_asset: &Address,
+        to_asset: &Address,
+        amount_in: i128,
+        amount_out: i128,
+    ) -> Result<(), Error> {
+        let Some(config) = Self::get_pair_oracle(env.clone(), from_asset.clone(), to_asset.clone())
+        else {
+            return Ok(());
+        };
+
+        let oracle = PriceOracleClient::new(env, &config.oracle);
+        let now = env.ledger().timestamp();
+        let price = |asset: &Address| {
+            oracle
+                .lastprice(&Asset::Stellar(asset.clone()))
+                .filter(|data| now.saturating_sub(data.timestamp) <= config.max_age_seconds)
+                .map(|data| data.price)
+                .filter(|price| *price > 0)
+                .ok_or(Error::OraclePriceUnavailable)
+        };
+        let (price_in, price_out) = (price(from_asset)?, price(to_asset)?);
+
+        // Output the swap would give at the oracle's price
+        let oracle_out = amount_in * price_in / price_out;
+        if oracle_out <= 0 {
+            return Err(Error::PriceDeviation);
+        }
+        let deviation_bps = (amount_out - oracle_out).abs() * BPS / oracle_out;
+        if deviation_bps > config.max_deviation_bps {
+            return Err(Error::PriceDeviation);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+use soroban_sdk::{vec, Address, Env, Vec};
+
+use crate::{
+    events,
+    pool::{BPS, MAX_BUMP},
+    DataKey, Error, MultiAssetRouter, SwapResult,
+};
+
+/// Upper bound on `MaxHops`, so a path search stays within the per-call
+/// footprint and budget limits
+pub const MAX_HOPS_LIMIT: u32 = 4;
+
+/// Depth-first search for the best route between two assets.
+///
+/// Exact-input searches walk from the input asset and keep the largest output.
+/// Exact-output searches walk back from the output asset and keep the smallest
+/// input.
+struct Search<'a> {
+    env: &'a Env,
+    target: Address,
+    exact_out: bool,
+    /// Assets a route may pass through
+    supported: Vec<Address>,
+    /// Amount reaching the target and the assets visited before it
+    best: Option<(i128, Vec<Address>)>,
+    /// Error to report if no route can be priced: no pools at all, or the
+    /// pools found could not fill the swap
+    error: Error,
+}
+
+impl Search<'_> {
+    /// Extend the route in `visited` by every pool out of its last asset
+    fn visit(&mut self, visited: &mut Vec<Address>, amount: i128, hops_left: u32) {
+        let asset = visited.last_unchecked();
+        for next in MultiAssetRouter::pool_assets(self.env, &asset).iter() {
+            if visited.contains(&next)
+                || !self.supported.contains(&next)
+                || (next != self.target && hops_left == 1)
+            {
+                continue;
+            }
+
+            let quote = MultiAssetRouter::get_pool(self.env.clone(), asset.clone(), next.clone())
+                .and_then(|pool| {
+                    if self.exact_out {
+                        pool.quote_exact_out(&next, amount)
+                    } else {
+                        Ok(pool.quote(&asset, amount)?.amount_out)
+                    }
+                });
+            let next_amount = match quote {
+                Ok(next_amount) => next_amount,
+                Err(e) => {
+                    self.error = e;
+                    continue;
+                }
+            };
+
+            if next == self.target {
+                let route = visited.slice(1..);
+                let better = match &self.best {
+                    Some((best_amount, best_route)) => {
+                        let improves = if self.exact_out {
+                            next_amount < *best_amount
+                        } else {
+                            next_amount > *best_amount
+                        };
+                        improves || (next_amount == *best_amount && route.len() < best_route.len())
+                    }
+                    None => true,
+                };
+                if better {
+                    self.best = Some((next_amount, route));
+                }
+            } else {
+                visited.push_back(next);
+                self.visit(visited, next_amount, hops_left - 1);
+                visited.pop_back();
+            }
+        }
+    }
+}
+
+impl MultiAssetRouter {
+    /// Assets that have a pool with `asset`
+    pub(crate) fn pool_assets(env: &Env, asset: &Address) -> Vec<Address> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::PoolAssets(asset.clone()))
+            .unwrap_or(Vec::new(env))
+    }
+
+    /// Record a new pool in the index the path search walks
+    pub(crate) fn index_pool(env: &Env, token_a: &Address, token_b: &Address) {
+        for (asset, other) in [(token_a, token_b), (token_b, token_a)] {
+            let mut assets = Self::pool_assets(env, asset);
+            assets.push_back(other.clone());
+            let key = DataKey::PoolAssets(asset.clone());
+            env.storage().persistent().set(&key, &assets);
+            env.storage()
+                .persistent()
+                .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+        }
+    }
+
+    /// Check both ends of a swap are on the allowlist, returning the allowlist
+    fn require_pair_supported(
+        env: &Env,
+        from_asset: &Address,
+        to_asset: &Address,
+    ) -> Result<Vec<Address>, Error> {
+        let supported = Self::list_supported_assets(env.clone());
+        if !supported.contains(from_asset) || !supported.contains(to_asset) {
+            return Err(Error::UnauthorizedAsset);
+        }
+        Ok(supported)
+    }
+
+    /// Find the intermediate assets of the route from `from_asset` to `to_asset`,
+    /// using at most `MaxHops` pools, with the largest output for `amount_in`.
+    /// Ties go to the shorter route.
+    pub(crate) fn best_path(
+        env: &Env,
+        from_asset: &Address,
+        to_asset: &Address,
+        amount_in: i128,
+    ) -> Result<Vec<Address>, Error> {
+        let supported = Self::require_pair_supported(env, from_asset, to_asset)?;
+        let mut search = Search {
+            env,
+            target: to_asset.clone(),
+            exact_out: false,
+            supported,
+            best: None,
+            error: Error::PoolNotFound,
+        };
+        search.visit(
+            &mut vec![env, from_asset.clone()],
+            amount_in,
+            Self::get_max_hops(env.clone()),
+        );
+        search.best.map(|(_, path)| path).ok_or(search.error)
+    }
+
+    /// Find the intermediate assets of the route from `from_asset` to `to_asset`,
+    /// using at most `MaxHops` pools, needing the smallest input for `amount_out`.
+    /// Ties go to the shorter route.
+    pub(crate) fn best_path_exact_out(
+        env: &Env,
+        from_asset: &Address,
+        to_asset: &Address,
+        amount_out: i128,
+    ) -> Result<Vec<Address>, Error> {
+        let supported = Self::require_pair_supported(env, from_asset, to_asset)?;
+        let mut search = Search {
+            env,
+            target: from_asset.clone(),
+            exact_out: true,
+            supported,
+            best: None,
+            error: Error::PoolNotFound,
+        };
+        search.visit(
+            &mut vec![env, to_asset.clone()],
+            amount_out,
+            Self::get_max_hops(env.clone()),
+        );
+
+        // The search walked backwards from the output asset
+        let (_, reversed) = search.best.ok_or(search.error)?;
+        let mut path = Vec::new(env);
+        for asset in reversed.iter() {
+            path.push_front(asset);
+        }
+        Ok(path)
+    }
+
+    /// Every asset a swap along `path` passes through, from first to last
+    pub(crate) fn hops(
+        env: &Env,
+        from_asset: &Address,
+        path: &Vec<Address>,
+        to_asset: &Address,
+    ) -> Vec<Address> {
+        let mut assets = vec![env, from_asset.clone()];
+        assets.append(path);
+        assets.push_back(to_asset.clone());
+        assets
+    }
+
+    /// Amounts entering each pool along `assets` for `amount_in`, followed by
+    /// the amount leaving the last one
+    pub(crate) fn amounts_exact_in(
+        env: &Env,
+        assets: &Vec<Address>,
+        amount_in: i128,
+    ) -> Result<Vec<i128>, Error> {
+        let mut amounts = vec![env, amount_in];
+        let mut amount = amount_in;
+        for (asset, next) in assets.iter().zip(assets.iter().skip(1)) {
+            amount = Self::get_pool(env.clone(), asset.clone(), next)?
+                .quote(&asset, amount)?
+                .amount_out;
+            amounts.push_back(amount);
+        }
+        Ok(amounts)
+    }
+
+    /// Amounts entering each pool along `assets` so exactly `amount_out` leaves
+    /// the last one, followed by `amount_out`
+    pub(crate) fn amounts_exact_out(
+        env: &Env,
+        assets: &Vec<Address>,
+        amount_out: i128,
+    ) -> Result<Vec<i128>, Error> {
+        let mut amounts = vec![env, amount_out];
+        let mut amount = amount_out;
+        for index in (1..assets.len()).rev() {
+            let (asset, next) = (assets.get_unchecked(index - 1), assets.get_unchecked(index));
+            amount = Self::get_pool(env.clone(), asset.clone(), next)?
+                .quote_exact_out(&asset, amount)?;
+            amounts.push_front(amount);
+        }
+        Ok(amounts)
+    }
+
+    /// Price a swap of `amounts` along `assets`, as returned by `amounts_exact_in`
+    /// or `amounts_exact_out`.
+    ///
+    /// `fees_paid` is the LP fee of every hop, in units of the input asset, and
+    /// `price_impact` compounds the impact of each hop.
+    pub(crate) fn price_hops(
+        env: &Env,
+        assets: &Vec<Address>,
+        amounts: &Vec<i128>,
+    ) -> Result<SwapResult, Error> {
+        let amount_in = amounts.first_unchecked();
+        let mut kept = BPS;
+        let mut net_in = amount_in;
+        for (hop_in, (asset, next)) in amounts.iter().zip(assets.iter().zip(assets.iter().skip(1)))
+        {
+            let hop = Self::get_pool(env.clone(), asset.clone(), next)?.quote(&asset, hop_in)?;
+            net_in -= hop.fees_paid * net_in / hop_in;
+            kept = kept * (BPS - hop.price_impact) / BPS;
+        }
+
+        Ok(SwapResult {
+            amount_out: amounts.last_unchecked(),
+            price_impact: BPS - kept,
+            fees_paid: amount_in - net_in,
+        })
+    }
+
+    /// Price a swap of `amount_in` along `path` off the current reserves
+    pub(crate) fn quote_path(
+        env: &Env,
+        from_asset: &Address,
+        path: &Vec<Address>,
+        to_asset: &Address,
+        amount_in: i128,
+    ) -> Result<SwapResult, Error> {
+        let assets = Self::hops(env, from_asset, path, to_asset);
+        let amounts = Self::amounts_exact_in(env, &assets, amount_in)?;
+        Self::price_hops(env, &assets, &amounts)
+    }
+
+    /// Move `amounts` through each pool along `assets` in turn. Each pool keeps its
+    /// LP fee, less the protocol's share, along with any rounding surplus of an
+    /// exact-output swap. Hops through a pair with an oracle must be priced close
+    /// to it.
+    pub(crate) fn swap_hops(
+        env: &Env,
+        assets: &Vec<Address>,
+        amounts: &Vec<i128>,
+    ) -> Result<(), Error> {
+        for index in 1..assets.len() {
+            let (asset, next) = (assets.get_unchecked(index - 1), assets.get_unchecked(index));
+            let amount_in = amounts.get_unchecked(index - 1);
+            let amount_out = amounts.get_unchecked(index);
+
+            let mut pool = Self::get_pool(env.clone(), asset.clone(), next.clone())?;
+            let hop = pool.quote(&asset, amount_in)?;
+            if hop.amount_out < amount_out {
+                return Err(Error::InsufficientLiquidity);
+            }
+            Self::check_oracle_price(env, &asset, &next, amount_in, amount_out)?;
+            let protocol_fee = Self::accrue_protocol_fee(env, &asset, hop.fees_paid);
+            Self::update_cumulative_prices(env, &mut pool);
+            pool.apply_swap(&asset, amount_in - protocol_fee, amount_out);
+            Self::save_pool(env, &pool);
+
+            events::Swap {
+                from_asset: asset,
+                to_asset: next,
+                amount_in,
+                amount_out,
+                fees_paid: hop.fees_paid,
+            }
+            .publish(env);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use admin_sep::AdministratableExtension;
+use soroban_sdk::{contractimpl, contracttype, token, xdr::ToXdr, Address, BytesN, Env, String};
+
+use crate::{
+    events, DataKey, Error, MultiAssetRouter, MultiAssetRouterArgs, MultiAssetRouterClient,
+    SwapResult,
+};
+
+pub const MAX_BUMP: u32 = 535_679;
+
+/// Basis-point denominator for fees and price impact
+pub const BPS: i128 = 10_000;
+
+/// Trading fee taken from the input of every swap and left in the pool for LPs,
+/// less the protocol fee share
+pub const LP_FEE_BPS: i128 = 30;
+
+/// Shares minted to the router on the first deposit and locked forever, so the
+/// share price can't be inflated to round later deposits down to nothing
+pub const MINIMUM_LIQUIDITY: i128 = 1_000;
+
+/// Decimals of every pool's share token
+pub const SHARE_DECIMALS: u32 = 7;
+
+/// Constant-product pool for a token pair, stored with `token_a < token_b`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pool {
+    pub token_a: Address,
+    pub token_b: Address,
+    pub reserve_a: i128,
+    pub reserve_b: i128,
+    pub total_shares: i128,
+    /// SEP-41 token of the pool's shares, minted and burned by the router
+    pub share_token: Address,
+    /// Sum over time of the price of `token_a` in `token_b`, for TWAPs
+    pub price_a_cumulative: i128,
+    /// Sum over time of the price of `token_b` in `token_a`, for TWAPs
+    pub price_b_cumulative: i128,
+    /// Seconds left out of `price_a_cumulative` because the price was too large to scale
+    pub price_a_skipped: u64,
+    /// Seconds left out of `price_b_cumulative` because the price was too large to scale
+    pub price_b_skipped: u64,
+    /// Ledger timestamp the cumulative prices were last brought up to
+    pub last_updated: u64,
+}
+
+impl Pool {
+    /// Reserves of the pool as `(reserve_in, reserve_out)` for a swap from `from_asset`
+    pub fn reserves_for(&self, from_asset: &Address) -> (i128, i128) {
+        if *from_asset == self.token_a {
+            (self.reserve_a, self.reserve_b)
+        } else {
+            (self.reserve_b, self.reserve_a)
+        }
+    }
+
+    /// Output of swapping `amount_in` of `from_asset` through the pool, after the LP fee.
+    ///
+    /// `price_impact` is how far the execution price falls short of the spot price,
+    /// excluding the fee.
+    pub fn quote(&self, from_asset: &Address, amount_in: i128) -> Result<SwapResult, Error> {
+        let (reserve_in, reserve_out) = self.reserves_for(from_asset);
+        if amount_in <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let amount_in_after_fee = amount_in * (BPS - LP_FEE_BPS) / BPS;
+        if reserve_in == 0 || reserve_out == 0 || amount_in_after_fee == 0 {
+            return Err(Error::InsufficientLiquidity);
+        }
+        let amount_out = amount_in_after_fee * reserve_out / (reserve_in + amount_in_after_fee);
+        if amount_out <= 0 {
+            return Err(Error::InsufficientLiquidity);
+        }
+
+        Ok(SwapResult {
+            amount_out,
+            price_impact: amount_in_after_fee * BPS / (reserve_in + amount_in_after_fee),
+            fees_paid: amount_in - amount_in_after_fee,
+        })
+    }
+
+    /// Input of `from_asset` needed for at least `amount_out` of the other token,
+    /// after the LP fee. Rounds up, so `quote` of the result never falls short.
+    pub fn quote_exact_out(&self, from_asset: &Address, amount_out: i128) -> Result<i128, Error> {
+        let (reserve_in, reserve_out) = self.reserves_for(from_asset);
+        if amount_out <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        if amount_out >= reserve_out {
+            return Err(Error::InsufficientLiquidity);
+        }
+
+        let amount_in_after_fee = div_ceil(reserve_in * amount_out, reserve_out - amount_out);
+        Ok(div_ceil(amount_in_after_fee * BPS, BPS - LP_FEE_BPS))
+    }
+
+    /// Move `amount_in` of `from_asset` into the reserves and `amount_out` of the
+    /// other token out of them
+    pub fn apply_swap(&mut self, from_asset: &Address, amount_in: i128, amount_out: i128) {
+        if *from_asset == self.token_a {
+            self.reserve_a += amount_in;
+            self.reserve_b -= amount_out;
+        } else {
+            self.reserve_b += amount_in;
+            self.reserve_a -= amount_out;
+        }
+    }
+
+    /// The token the pool swaps `asset` into
+    pub fn other(&self, asset: &Address) -> Address {
+        if *asset == self.token_a {
+            self.token_b.clone()
+        } else {
+            self.token_a.clone()
+        }
+    }
+}
+
+/// Order a token pair the way pools are keyed
+pub fn sort_pair(token_a: Address, token_b: Address) -> (Address, Address) {
+    if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    }
+}
+
+fn div_ceil(numerator: i128, denominator: i128) -> i128 {
+    (numerator + denominator - 1) / denominator
+}
+
+fn isqrt(value: i128) -> i128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = (x + 1) / 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+#[contractimpl]
+impl MultiAssetRouter {
+    /// Set the wasm every new pool's share token is deployed from (admin only)
+    pub fn set_share_token_wasm(env: Env, wasm_hash: BytesN<32>) {
+        Self::require_admin(&env);
+        env.storage()
+            .instance()
+            .set(&DataKey::ShareTokenWasm, &wasm_hash);
+    }
+
+    /// Get the wasm new share tokens are deployed from, if set
+    pub fn get_share_token_wasm(env: Env) -> Option<BytesN<32>> {
+        env.storage().instance().get(&DataKey::ShareTokenWasm)
+    }
+
+    /// Create an empty pool for a token pair, deploying its share token. Anyone
+    /// may create a pool. Fails with `ShareTokenNotSet` until the admin has set
+    /// the share token wasm.
+    pub fn create_pool(env: Env, token_a: Address, token_b: Address) -> Result<(), Error> {
+        if token_a == token_b {
+            return Err(Error::InvalidAsset);
+        }
+        let (token_a, token_b) = sort_pair(token_a, token_b);
+        let key = DataKey::Pool(token_a.clone(), token_b.clone());
+        if env.storage().persistent().has(&key) {
+            return Err(Error::PoolExists);
+        }
+        let wasm_hash = Self::get_share_token_wasm(env.clone()).ok_or(Error::ShareTokenNotSet)?;
+
+        // One share token per pair, at an address derived from the pair
+        let salt = env
+            .crypto()
+            .sha256(&(token_a.clone(), token_b.clone()).to_xdr(&env));
+        let share_token = env.deployer().with_current_contract(salt).deploy_v2(
+            wasm_hash,
+            (
+                env.current_contract_address(),
+                SHARE_DECIMALS,
+                String::from_str(&env, "Multi Asset Router Pool Share"),
+                String::from_str(&env, "POOL"),
+            ),
+        );
+
+        Self::save_pool(
+            &env,
+            &Pool {
+                token_a: token_a.clone(),
+                token_b: token_b.clone(),
+                reserve_a: 0,
+                reserve_b: 0,
+                total_shares: 0,
+                share_token: share_token.clone(),
+                price_a_cumulative: 0,
+                price_b_cumulative: 0,
+                price_a_skipped: 0,
+                price_b_skipped: 0,
+                last_updated: env.ledger().timestamp(),
+            },
+        );
+        Self::index_pool(&env, &token_a, &token_b);
+
+        events::PoolCreated {
+            token_a,
+            token_b,
+            share_token,
+        }
+        .publish(&env);
+        Ok(())
+    }
+
+    /// Get the pool for a token pair, in either order
+    pub fn get_pool(env: Env, token_a: Address, token_b: Address) -> Result<Pool, Error> {
+        let (token_a, token_b) = sort_pair(token_a, token_b);
+        let key = DataKey::Pool(token_a, token_b);
+        let pool = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .ok_or(Error::PoolNotFound)?;
+        env.storage()
+            .persistent()
+            .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+        Ok(pool)
+    }
+
+    /// Get the pool shares held by `provider`: its balance of the share token
+    pub fn get_shares(env: Env, token_a: Address, token_b: Address, provider: Address) -> i128 {
+        Self::get_pool(env.clone(), token_a, token_b)
+            .map(|pool| token::Client::new(&env, &pool.share_token).balance(&provider))
+            .unwrap_or(0)
+    }
+
+    /// Deposit up to `amount_a` of `token_a` and `amount_b` of `token_b` for pool shares,
+    /// minted to `provider` as the pool's share token.
+    ///
+    /// After the first deposit, the amounts are reduced to the pool's current ratio.
+    /// Fails with `SlippageExceeded` if fewer than `min_shares` would be minted.
+    /// Returns the amounts deposited and the shares minted.
+    pub fn add_liquidity(
+        env: Env,
+        provider: Address,
+        token_a: Address,
+        token_b: Address,
+        amount_a: i128,
+        amount_b: i128,
+        min_shares: i128,
+    ) -> Result<(i128, i128, i128), Error> {
+        provider.require_auth();
+
+        if amount_a <= 0 || amount_b <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut pool = Self::get_pool(env.clone(), token_a.clone(), token_b.clone())?;
+        let (desired_a, desired_b) = if token_a == pool.token_a {
+            (amount_a, amount_b)
+        } else {
+            (amount_b, amount_a)
+        };
+
+        let first_deposit = pool.total_shares == 0;
+        let (deposit_a, deposit_b, shares) = if first_deposit {
+            let shares = isqrt(desired_a * desired_b) - MINIMUM_LIQUIDITY;
+            (desired_a, desired_b, shares)
+        } else {
+            let optimal_b = desired_a * pool.reserve_b / pool.reserve_a;
+            let (deposit_a, deposit_b) = if optimal_b <= desired_b {
+                (desired_a, optimal_b)
+            } else {
+                (desired_b * pool.reserve_a / pool.reserve_b, desired_b)
+            };
+            let shares = (deposit_a * pool.total_shares / pool.reserve_a)
+                .min(deposit_b * pool.total_shares / pool.reserve_b);
+            (deposit_a, deposit_b, shares)
+        };
+        if shares <= 0 {
+            return Err(Error::InsufficientLiquidity);
+        }
+        if shares < min_shares {
+            return Err(Error::SlippageExceeded);
+        }
+
+        let contract_address = env.current_contract_address();
+        token::Client::new(&env, &pool.token_a).transfer(&provider, &contract_address, &deposit_a);
+        token::Client::new(&env, &pool.token_b).transfer(&provider, &contract_address, &deposit_b);
+
+        let share_token = token::StellarAssetClient::new(&env, &pool.share_token);
+        if first_deposit {
+            share_token.mint(&contract_address, &MINIMUM_LIQUIDITY);
+            pool.total_shares = MINIMUM_LIQUIDITY;
+        }
+        share_token.mint(&provider, &shares);
+
+        Self::update_cumulative_prices(&env, &mut pool);
+        pool.reserve_a += deposit_a;
+        pool.reserve_b += deposit_b;
+        pool.total_shares += shares;
+        Self::save_pool(&env, &pool);
+
+        events::LiquidityAdded {
+            provider,
+            token_a: pool.token_a.clone(),
+            token_b: pool.token_b.clone(),
+            amount_a: deposit_a,
+            amount_b: deposit_b,
+            shares,
+        }
+        .publish(&env);
+
+        if token_a == pool.token_a {
+            Ok((deposit_a, deposit_b, shares))
+        } else {
+            Ok((deposit_b, deposit_a, shares))
+        }
+    }
+
+    /// Burn `shares` of the pool's share token for the provider's part of both reserves.
+    ///
+    /// Fails with `SlippageExceeded` if less than `min_a` of `token_a` or `min_b` of
+    /// `token_b` would be returned. Returns the amounts withdrawn.
+    pub fn remove_liquidity(
+        env: Env,
+        provider: Address,
+        token_a: Address,
+        token_b: Address,
+        shares: i128,
+        min_a: i128,
+        min_b: i128,
+    ) -> Result<(i128, i128), Error> {
+        provider.require_auth();
+
+        if shares <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let mut pool = Self::get_pool(env.clone(), token_a.clone(), token_b.clone())?;
+        let share_token = token::Client::new(&env, &pool.share_token);
+        if share_token.balance(&provider) < shares {
+            return Err(Error::InsufficientShares);
+        }
+
+        let withdraw_a = shares * pool.reserve_a / pool.total_shares;
+        let withdraw_b = shares * pool.reserve_b / pool.total_shares;
+        let (out_a, out_b) = if token_a == pool.token_a {
+            (withdraw_a, withdraw_b)
+        } else {
+            (withdraw_b, withdraw_a)
+        };
+        if out_a < min_a || out_b < min_b {
+            return Err(Error::SlippageExceeded);
+        }
+
+        share_token.burn(&provider, &shares);
+        Self::update_cumulative_prices(&env, &mut pool);
+        pool.reserve_a -= withdraw_a;
+        pool.reserve_b -= withdraw_b;
+        pool.total_shares -= shares;
+        Self::save_pool(&env, &pool);
+
+        let contract_address = env.current_contract_address();
+        token::Client::new(&env, &pool.token_a).transfer(&contract_address, &provider, &withdraw_a);
+        token::Client::new(&env, &pool.token_b).transfer(&contract_address, &provider, &withdraw_b);
+
+        events::LiquidityRemoved {
+            provider,
+            token_a: pool.token_a.clone(),
+            token_b: pool.token_b.clone(),
+            amount_a: withdraw_a,
+            amount_b: withdraw_b,
+            shares,
+        }
+        .publish(&env);
+
+        Ok((out_a, out_b))
+    }
+}
+
+impl MultiAssetRouter {
+    pub(crate) fn save_pool(env: &Env, pool: &Pool) {
+        let key = DataKey::Pool(pool.token_a.clone(), pool.token_b.clone());
+        env.storage().persistent().set(&key, pool);
+        env.storage()
+            .persistent()
+            .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+    }
+}
@@ -0,0 +1,258 @@
+// The swap entry points take more arguments than clippy allows, and a
+// function-level `#[allow]` doesn't reach the argument helpers `contractargs`
+// generates for them
+#![allow(clippy::too_many_arguments)]
+
+use soroban_sdk::{contractimpl, log, token, Address, Env, Vec};
+
+use crate::{
+    pool::BPS, Error, MultiAssetRouter, MultiAssetRouterArgs, MultiAssetRouterClient,
+    SwapExactOutResult, SwapLeg, SwapPath, SwapResult,
+};
+
+#[contractimpl]
+impl MultiAssetRouter {
+    /// Execute a token swap with automatic path finding.
+    ///
+    /// The output must be at least `min_amount_out`, and at most `slippage_bps`
+    /// below `quoted_amount_out`, the output the caller was quoted by
+    /// `get_swap_quote` before submitting the swap. The default slippage
+    /// tolerance applies if `slippage_bps` is `None`. Fails with
+    /// `InvalidSlippage` above the maximum slippage, or `DeadlinePassed` once
+    /// the ledger timestamp is past `deadline`.
+    pub fn execute_swap(
+        env: Env,
+        from_asset: Address,
+        to_asset: Address,
+        amount_in: i128,
+        min_amount_out: i128,
+        quoted_amount_out: i128,
+        slippage_bps: Option<i128>,
+        payer: Address,
+        recipient: Address,
+        deadline: u64,
+    ) -> Result<SwapResult, Error> {
+        payer.require_auth();
+        Self::check_deadline(&env, deadline)?;
+
+        Self::swap(
+            &env,
+            from_asset,
+            to_asset,
+            amount_in,
+            min_amount_out,
+            quoted_amount_out,
+            slippage_bps,
+            payer,
+            recipient,
+        )
+    }
+
+    /// Execute several swaps for one payer, such as paying many recipients in
+    /// their own assets, with a single authorization.
+    ///
+    /// Each leg is executed like `execute_swap`, in order, so later legs price
+    /// off the reserves earlier legs left behind. If any leg fails, including
+    /// on slippage, the whole batch is reverted. Fails with `DeadlinePassed`
+    /// once the ledger timestamp is past `deadline`.
+    pub fn execute_batch_swap(
+        env: Env,
+        payer: Address,
+        legs: Vec<SwapLeg>,
+        deadline: u64,
+    ) -> Result<Vec<SwapResult>, Error> {
+        payer.require_auth();
+        Self::check_deadline(&env, deadline)?;
+
+        let mut results = Vec::new(&env);
+        for leg in legs.iter() {
+            results.push_back(Self::swap(
+                &env,
+                leg.from_asset,
+                leg.to_asset,
+                leg.amount_in,
+                leg.min_amount_out,
+                leg.quoted_amount_out,
+                leg.slippage_bps,
+                payer.clone(),
+                leg.recipient,
+            )?);
+        }
+        Ok(results)
+    }
+
+    /// Swap as little of `from_asset` as needed for exactly `amount_out` of `to_asset`.
+    ///
+    /// Takes the cheapest path up to MaxHops deep and only transfers the input it
+    /// needs, so there is nothing to refund. Fails with `SlippageExceeded` if
+    /// more than `max_amount_in` would be needed, or `DeadlinePassed` once the
+    /// ledger timestamp is past `deadline`.
+    pub fn execute_swap_exact_out(
+        env: Env,
+        from_asset: Address,
+        to_asset: Address,
+        amount_out: i128,
+        max_amount_in: i128,
+        payer: Address,
+        recipient: Address,
+        deadline: u64,
+    ) -> Result<SwapExactOutResult, Error> {
+        payer.require_auth();
+        Self::check_deadline(&env, deadline)?;
+
+        if amount_out <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        // If same asset, just transfer
+        if from_asset == to_asset {
+            Self::require_supported(&env, &from_asset)?;
+            if amount_out > max_amount_in {
+                return Err(Error::SlippageExceeded);
+            }
+            token::Client::new(&env, &from_asset).transfer(&payer, &recipient, &amount_out);
+
+            return Ok(SwapExactOutResult {
+                amount_in: amount_out,
+                price_impact: 0,
+                fees_paid: 0,
+            });
+        }
+
+        let path = Self::best_path_exact_out(&env, &from_asset, &to_asset, amount_out)?;
+        let assets = Self::hops(&env, &from_asset, &path, &to_asset);
+        let amounts = Self::amounts_exact_out(&env, &assets, amount_out)?;
+        let amount_in = amounts.first_unchecked();
+        if amount_in > max_amount_in {
+            return Err(Error::SlippageExceeded);
+        }
+        let result = Self::price_hops(&env, &assets, &amounts)?;
+
+        let contract_address = env.current_contract_address();
+        token::Client::new(&env, &from_asset).transfer(&payer, &contract_address, &amount_in);
+        Self::swap_hops(&env, &assets, &amounts)?;
+        token::Client::new(&env, &to_asset).transfer(&contract_address, &recipient, &amount_out);
+
+        log!(
+            &env,
+            "Swap executed: {} -> {}, amount: {}",
+            from_asset,
+            to_asset,
+            amount_out
+        );
+
+        Ok(SwapExactOutResult {
+            amount_in,
+            price_impact: result.price_impact,
+            fees_paid: result.fees_paid,
+        })
+    }
+}
+
+impl MultiAssetRouter {
+    /// Swap `amount_in` of `from_asset` along the best path for at least
+    /// `min_amount_out` of `to_asset`, and within `slippage_bps` of
+    /// `quoted_amount_out`, once the payer's authorization and the deadline
+    /// have been checked
+    fn swap(
+        env: &Env,
+        from_asset: Address,
+        to_asset: Address,
+        amount_in: i128,
+        min_amount_out: i128,
+        quoted_amount_out: i128,
+        slippage_bps: Option<i128>,
+        payer: Address,
+        recipient: Address,
+    ) -> Result<SwapResult, Error> {
+        if amount_in <= 0 {
+            return Err(Error::InvalidAsset);
+        }
+        if quoted_amount_out < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let slippage_tolerance = Self::slippage_tolerance(env, slippage_bps)?;
+        let min_amount_out =
+            min_amount_out.max(quoted_amount_out * (BPS - slippage_tolerance) / BPS);
+
+        // If same asset, just transfer
+        if from_asset == to_asset {
+            Self::require_supported(env, &from_asset)?;
+            if amount_in < min_amount_out {
+                return Err(Error::SlippageExceeded);
+            }
+            let token_client = token::Client::new(env, &from_asset);
+            token_client.transfer(&payer, &recipient, &amount_in);
+
+            return Ok(SwapResult {
+                amount_out: amount_in,
+                price_impact: 0,
+                fees_paid: 0,
+            });
+        }
+
+        // Find optimal swap path
+        let path = Self::best_path(env, &from_asset, &to_asset, amount_in)?;
+        let swap_path = SwapPath {
+            from_asset: from_asset.clone(),
+            to_asset: to_asset.clone(),
+            amount_in,
+            min_amount_out,
+            path,
+        };
+
+        // Execute the swap
+        let result = Self::execute_swap_path(env, swap_path, payer, recipient)?;
+
+        // Check slippage
+        if result.amount_out < min_amount_out {
+            return Err(Error::SlippageExceeded);
+        }
+
+        log!(
+            env,
+            "Swap executed: {} -> {}, amount: {}",
+            from_asset,
+            to_asset,
+            result.amount_out
+        );
+
+        Ok(result)
+    }
+
+    /// Execute a swap along a specific path
+    fn execute_swap_path(
+        env: &Env,
+        swap_path: SwapPath,
+        payer: Address,
+        recipient: Address,
+    ) -> Result<SwapResult, Error> {
+        // Transfer input tokens from payer to contract
+        let contract_address = env.current_contract_address();
+        token::Client::new(env, &swap_path.from_asset).transfer(
+            &payer,
+            &contract_address,
+            &swap_path.amount_in,
+        );
+
+        // Swap through each pool in turn, pricing off its reserves
+        let assets = Self::hops(
+            env,
+            &swap_path.from_asset,
+            &swap_path.path,
+            &swap_path.to_asset,
+        );
+        let amounts = Self::amounts_exact_in(env, &assets, swap_path.amount_in)?;
+        let result = Self::price_hops(env, &assets, &amounts)?;
+        Self::swap_hops(env, &assets, &amounts)?;
+
+        // Transfer output tokens to recipient
+        token::Client::new(env, &swap_path.to_asset).transfer(
+            &contract_address,
+            &recipient,
+            &result.amount_out,
+        );
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,1350 @@
+extern crate std;
+
+use crate::{
+    events,
+    oracle::{Asset, PriceData},
+    path::MAX_HOPS_LIMIT,
+    pool::MINIMUM_LIQUIDITY,
+    twap::PRICE_SCALE,
+    Error, MultiAssetRouter, MultiAssetRouterClient, OracleConfig, Pool, SwapExactOutResult,
+    SwapLeg, SwapResult,
+};
+use soroban_sdk::{
+    contract, contractimpl, contracttype,
+    testutils::{Address as _, Events as _, Ledger},
+    token::{StellarAssetClient, TokenClient},
+    vec, Address, Bytes, Env, Event,
+};
+
+mod share_token {
+    soroban_sdk::contractimport!(file = "../../target/stellar/local/share_token.wasm");
+}
+
+/// SEP-40 price feed whose prices the tests set directly, timestamped when set
+#[contract]
+pub struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    pub fn set_price(env: Env, asset: Address, price: i128) {
+        let data = PriceData {
+            price,
+            timestamp: env.ledger().timestamp(),
+        };
+        env.storage().instance().set(&asset, &data);
+    }
+
+    pub fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+        let Asset::Stellar(asset) = asset else {
+            return None;
+        };
+        env.storage().instance().get(&asset)
+    }
+}
+
+/// How `MockReceiver` handles a flash swap
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Behavior {
+    Repay,
+    Underpay,
+    Reenter,
+}
+
+/// Flash swap receiver that repays, underpays, or tries to swap through the
+/// pool it borrowed from
+#[contract]
+pub struct MockReceiver;
+
+#[contractimpl]
+impl MockReceiver {
+    pub fn __constructor(env: Env, router: Address, behavior: Behavior) {
+        env.storage().instance().set(&0u32, &router);
+        env.storage().instance().set(&1u32, &behavior);
+    }
+
+    pub fn on_flash_swap(
+        env: Env,
+        _initiator: Address,
+        token_out: Address,
+        amount_out: i128,
+        token_in: Address,
+        amount_owed: i128,
+        _data: Bytes,
+    ) {
+        let router: Address = env.storage().instance().get(&0u32).unwrap();
+        let behavior: Behavior = env.storage().instance().get(&1u32).unwrap();
+        let receiver = env.current_contract_address();
+
+        let repay = match behavior {
+            Behavior::Repay => amount_owed,
+            Behavior::Underpay => amount_owed - 1,
+            Behavior::Reenter => {
+                MultiAssetRouterClient::new(&env, &router).execute_swap(
+                    &token_out,
+                    &token_in,
+                    &amount_out,
+                    &0,
+                    &0,
+                    &None,
+                    &receiver,
+                    &receiver,
+                    &env.ledger().timestamp(),
+                );
+                amount_owed
+            }
+        };
+        TokenClient::new(&env, &token_in).transfer(&receiver, &router, &repay);
+    }
+}
+
+struct Setup<'a> {
+    env: Env,
+    client: MultiAssetRouterClient<'a>,
+    token_a: TokenClient<'a>,
+    token_b: TokenClient<'a>,
+    token_c: TokenClient<'a>,
+    lp: Address,
+    trader: Address,
+}
+
+impl<'a> Setup<'a> {
+    fn new() -> Self {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+        let client =
+            MultiAssetRouterClient::new(&env, &env.register(MultiAssetRouter, (admin.clone(),)));
+        client.set_share_token_wasm(&env.deployer().upload_contract_wasm(share_token::WASM));
+
+        let lp = Address::generate(&env);
+        let trader = Address::generate(&env);
+        let setup = Setup {
+            token_a: Self::token(&env),
+            token_b: Self::token(&env),
+            token_c: Self::token(&env),
+            env,
+            client,
+            lp,
+            trader,
+        };
+        for token in [&setup.token_a, &setup.token_b, &setup.token_c] {
+            setup.mint(token, &setup.lp, 10_000_000);
+            setup.mint(token, &setup.trader, 1_000_000);
+            setup.client.add_supported_asset(&token.address);
+        }
+        setup
+    }
+
+    fn token(env: &Env) -> TokenClient<'a> {
+        let address = env
+            .register_stellar_asset_contract_v2(Address::generate(env))
+            .address();
+        TokenClient::new(env, &address)
+    }
+
+    fn mint(&self, token: &TokenClient, to: &Address, amount: i128) {
+        StellarAssetClient::new(&self.env, &token.address).mint(to, &amount);
+    }
+
+    /// Create the A/B pool and seed it from the LP
+    fn pool(&self, amount_a: i128, amount_b: i128) -> (i128, i128, i128) {
+        self.add_pool(&self.token_a, &self.token_b, amount_a, amount_b)
+    }
+
+    /// Create a pool for any two tokens and seed it from the LP
+    fn add_pool(
+        &self,
+        token_a: &TokenClient,
+        token_b: &TokenClient,
+        amount_a: i128,
+        amount_b: i128,
+    ) -> (i128, i128, i128) {
+        self.client.create_pool(&token_a.address, &token_b.address);
+        self.client.add_liquidity(
+            &self.lp,
+            &token_a.address,
+            &token_b.address,
+            &amount_a,
+            &amount_b,
+            &0,
+        )
+    }
+
+    /// A flash swap receiver with some of every token to repay from
+    fn receiver(&self, behavior: Behavior) -> Address {
+        let receiver = self
+            .env
+            .register(MockReceiver, (self.client.address.clone(), behavior));
+        for token in [&self.token_a, &self.token_b, &self.token_c] {
+            self.mint(token, &receiver, 100_000);
+        }
+        receiver
+    }
+
+    fn get_pool(&self) -> Pool {
+        self.client
+            .get_pool(&self.token_a.address, &self.token_b.address)
+    }
+
+    /// Assert the events this contract emitted during the last invocation
+    fn assert_events(&self, expected: &[&dyn Event]) {
+        let expected: std::vec::Vec<_> = expected
+            .iter()
+            .map(|event| event.to_xdr(&self.env, &self.client.address))
+            .collect();
+        assert_eq!(
+            self.env
+                .events()
+                .all()
+                .filter_by_contract(&self.client.address),
+            expected
+        );
+    }
+
+    /// A swap deadline a few minutes from now
+    fn deadline(&self) -> u64 {
+        self.env.ledger().timestamp() + 300
+    }
+
+    fn swap(&self, amount_in: i128, min_amount_out: i128) -> Result<SwapResult, Error> {
+        match self.client.try_execute_swap(
+            &self.token_a.address,
+            &self.token_b.address,
+            &amount_in,
+            &min_amount_out,
+            &0,
+            &None,
+            &self.trader,
+            &self.trader,
+            &self.deadline(),
+        ) {
+            Ok(result) => Ok(result.unwrap()),
+            Err(error) => Err(error.unwrap()),
+        }
+    }
+}
+
+#[test]
+fn add_and_remove_liquidity() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+
+    // sqrt(1 000 000 * 4 000 000), less the locked minimum liquidity
+    assert_eq!(
+        setup.pool(1_000_000, 4_000_000),
+        (1_000_000, 4_000_000, 1_999_000)
+    );
+    assert_eq!(setup.client.get_shares(a, b, &setup.lp), 1_999_000);
+
+    // Later deposits are cut down to the pool ratio, in the caller's token order
+    let other = Address::generate(&setup.env);
+    setup.mint(&setup.token_a, &other, 500_000);
+    setup.mint(&setup.token_b, &other, 2_500_000);
+    assert_eq!(
+        setup
+            .client
+            .add_liquidity(&other, b, a, &2_500_000, &500_000, &0),
+        (2_000_000, 500_000, 1_000_000)
+    );
+    assert_eq!(setup.token_b.balance(&other), 500_000);
+
+    let pool = setup.get_pool();
+    assert_eq!(pool.total_shares, 3_000_000);
+    assert_eq!(
+        (pool.reserve_a, pool.reserve_b),
+        if pool.token_a == *a {
+            (1_500_000, 6_000_000)
+        } else {
+            (6_000_000, 1_500_000)
+        }
+    );
+
+    assert_eq!(
+        setup
+            .client
+            .try_remove_liquidity(&other, a, b, &1_000_001, &0, &0),
+        Err(Ok(Error::InsufficientShares))
+    );
+    assert_eq!(
+        setup
+            .client
+            .try_remove_liquidity(&other, a, b, &1_000_000, &500_001, &0),
+        Err(Ok(Error::SlippageExceeded))
+    );
+    assert_eq!(
+        setup
+            .client
+            .remove_liquidity(&other, a, b, &1_000_000, &500_000, &2_000_000),
+        (500_000, 2_000_000)
+    );
+    assert_eq!(setup.client.get_shares(a, b, &other), 0);
+    assert_eq!(setup.token_a.balance(&other), 500_000);
+    assert_eq!(setup.token_b.balance(&other), 2_500_000);
+}
+
+#[test]
+fn add_liquidity_checks_min_shares() {
+    let setup = Setup::new();
+    setup.pool(1_000_000, 1_000_000);
+
+    assert_eq!(
+        setup.client.try_add_liquidity(
+            &setup.lp,
+            &setup.token_a.address,
+            &setup.token_b.address,
+            &1_000,
+            &1_000,
+            &1_001,
+        ),
+        Err(Ok(Error::SlippageExceeded))
+    );
+    assert_eq!(
+        setup.client.try_add_liquidity(
+            &setup.lp,
+            &setup.token_a.address,
+            &setup.token_b.address,
+            &0,
+            &1_000,
+            &0,
+        ),
+        Err(Ok(Error::InvalidAmount))
+    );
+}
+
+#[test]
+fn shares_are_a_transferable_token() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    assert_eq!(
+        setup.client.try_create_pool(a, a),
+        Err(Ok(Error::InvalidAsset))
+    );
+    setup.pool(1_000_000, 1_000_000);
+
+    let pool = setup.get_pool();
+    let shares = TokenClient::new(&setup.env, &pool.share_token);
+    assert_eq!(shares.decimals(), 7);
+    assert_eq!(shares.balance(&setup.lp), 999_000);
+    // The locked minimum liquidity is held by the router, so the token's
+    // supply starts out matching the pool's
+    assert_eq!(shares.balance(&setup.client.address), 1_000);
+    // Only the router can burn shares on a holder's behalf
+    assert!(shares.try_burn_from(&setup.lp, &setup.lp, &1_000).is_err());
+
+    // Whoever holds the shares can withdraw them
+    let holder = Address::generate(&setup.env);
+    shares.transfer(&setup.lp, &holder, &499_000);
+    assert_eq!(setup.client.get_shares(a, b, &holder), 499_000);
+    assert_eq!(
+        setup
+            .client
+            .remove_liquidity(&holder, b, a, &499_000, &0, &0),
+        (499_000, 499_000)
+    );
+    assert_eq!(shares.balance(&holder), 0);
+    assert_eq!(setup.token_a.balance(&holder), 499_000);
+    assert_eq!(setup.get_pool().total_shares, 501_000);
+}
+
+#[test]
+fn pools_need_the_share_token_wasm() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let client = MultiAssetRouterClient::new(
+        &env,
+        &env.register(MultiAssetRouter, (Address::generate(&env),)),
+    );
+    assert_eq!(
+        client.try_create_pool(&Address::generate(&env), &Address::generate(&env)),
+        Err(Ok(Error::ShareTokenNotSet))
+    );
+}
+
+#[test]
+fn swap_prices_off_reserves() {
+    let setup = Setup::new();
+    setup.pool(1_000_000, 1_000_000);
+
+    // 9 970 after the 0.3% fee buys 9 970 * 1 000 000 / 1 009 970 of B
+    let expected = SwapResult {
+        amount_out: 9_871,
+        price_impact: 98,
+        fees_paid: 30,
+    };
+    assert_eq!(
+        setup
+            .client
+            .get_swap_quote(&setup.token_a.address, &setup.token_b.address, &10_000),
+        expected
+    );
+    assert_eq!(setup.swap(10_000, 9_871), Ok(expected));
+    assert_eq!(setup.token_b.balance(&setup.trader), 1_009_871);
+    assert_eq!(setup.token_a.balance(&setup.trader), 990_000);
+
+    // The whole input, fee included, stays in the pool
+    let pool = setup.get_pool();
+    let (reserve_a, reserve_b) = pool.reserves_for(&setup.token_a.address);
+    assert_eq!((reserve_a, reserve_b), (1_010_000, 990_129));
+
+    // Larger trades move the price further: 99 700 / 1 109 700
+    let quote =
+        setup
+            .client
+            .get_swap_quote(&setup.token_a.address, &setup.token_b.address, &100_000);
+    assert_eq!(quote.price_impact, 898);
+}
+
+#[test]
+fn swap_fails_without_liquidity_or_above_slippage() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+
+    assert_eq!(setup.swap(1_000, 0), Err(Error::PoolNotFound));
+    assert_eq!(
+        setup.client.try_create_pool(a, a),
+        Err(Ok(Error::InvalidAsset))
+    );
+
+    setup.client.create_pool(a, b);
+    assert_eq!(
+        setup.client.try_create_pool(b, a),
+        Err(Ok(Error::PoolExists))
+    );
+    assert_eq!(setup.swap(1_000, 0), Err(Error::InsufficientLiquidity));
+    // Even amounts too small to leave anything after the fee
+    assert_eq!(setup.swap(1, 0), Err(Error::InsufficientLiquidity));
+    assert_eq!(
+        setup.client.try_get_swap_quote(a, b, &3),
+        Err(Ok(Error::InsufficientLiquidity))
+    );
+
+    setup
+        .client
+        .add_liquidity(&setup.lp, a, b, &1_000_000, &1_000_000, &0);
+    assert_eq!(setup.swap(10_000, 9_872), Err(Error::SlippageExceeded));
+    assert_eq!(setup.token_a.balance(&setup.trader), 1_000_000);
+}
+
+#[test]
+fn same_asset_swaps_and_quotes_are_plain_transfers() {
+    let setup = Setup::new();
+    let a = &setup.token_a.address;
+    let recipient = Address::generate(&setup.env);
+
+    // No pool is needed to swap an asset for itself
+    let expected = SwapResult {
+        amount_out: 10_000,
+        price_impact: 0,
+        fees_paid: 0,
+    };
+    assert_eq!(setup.client.get_swap_quote(a, a, &10_000), expected);
+    assert_eq!(
+        setup.client.get_swap_quote_exact_out(a, a, &10_000),
+        SwapExactOutResult {
+            amount_in: 10_000,
+            price_impact: 0,
+            fees_paid: 0,
+        }
+    );
+    assert_eq!(
+        setup.client.execute_swap(
+            a,
+            a,
+            &10_000,
+            &10_000,
+            &0,
+            &None,
+            &setup.trader,
+            &recipient,
+            &setup.deadline()
+        ),
+        expected
+    );
+    assert_eq!(setup.token_a.balance(&recipient), 10_000);
+
+    assert_eq!(
+        setup.client.try_get_swap_quote(a, a, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+    setup.client.remove_supported_asset(a);
+    assert_eq!(
+        setup.client.try_get_swap_quote_exact_out(a, a, &10_000),
+        Err(Ok(Error::UnauthorizedAsset))
+    );
+}
+
+#[test]
+fn lp_fees_accrue_to_liquidity_providers() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    let (_, _, shares) = setup.pool(1_000_000, 1_000_000);
+
+    // Trade back and forth so the price returns close to where it started
+    for _ in 0..5 {
+        let out = setup.swap(100_000, 0).unwrap().amount_out;
+        setup.client.execute_swap(
+            b,
+            a,
+            &out,
+            &0,
+            &0,
+            &None,
+            &setup.trader,
+            &setup.trader,
+            &setup.deadline(),
+        );
+    }
+
+    let (out_a, out_b) = setup
+        .client
+        .remove_liquidity(&setup.lp, a, b, &shares, &0, &0);
+    let deposited = 1_000_000 * 999_000 / 1_000_000;
+    assert!(out_a * out_b > deposited * deposited);
+    assert!(setup.token_a.balance(&setup.trader) < 1_000_000);
+}
+
+#[test]
+fn protocol_takes_its_share_of_the_lp_fee() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+
+    assert_eq!(
+        setup.client.try_set_protocol_fee_share(&10_001),
+        Err(Ok(Error::InvalidAmount))
+    );
+    setup.client.set_protocol_fee_share(&5_000);
+    assert_eq!(setup.client.get_protocol_fee_share(), 5_000);
+
+    // The trader gets the same output, but half the 30 fee leaves the pool
+    assert_eq!(setup.swap(10_000, 9_871).unwrap().fees_paid, 30);
+    assert_eq!(setup.get_pool().reserves_for(a), (1_009_985, 990_129));
+    assert_eq!(setup.client.get_protocol_fees(a), 15);
+    assert_eq!(setup.client.get_protocol_fees(b), 0);
+
+    let treasury = Address::generate(&setup.env);
+    assert_eq!(setup.client.collect_protocol_fees(a, &treasury), 15);
+    setup.assert_events(&[&events::ProtocolFeesCollected {
+        asset: a.clone(),
+        to: treasury.clone(),
+        amount: 15,
+    }]);
+    assert_eq!(setup.token_a.balance(&treasury), 15);
+    assert_eq!(setup.client.get_protocol_fees(a), 0);
+    assert_eq!(setup.client.collect_protocol_fees(a, &treasury), 0);
+
+    // The pool's reserves are all that is left to its LPs
+    assert_eq!(setup.token_a.balance(&setup.client.address), 1_009_985);
+}
+
+#[test]
+#[should_panic]
+fn only_the_admin_can_collect_protocol_fees() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let client = MultiAssetRouterClient::new(&env, &env.register(MultiAssetRouter, (admin,)));
+    client.collect_protocol_fees(&Address::generate(&env), &Address::generate(&env));
+}
+
+#[test]
+fn flash_swap_lends_output_until_the_callback_repays_it() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+    let receiver = setup.receiver(Behavior::Repay);
+
+    // Owes what an exact-output swap for the 9 871 B would cost
+    let owed = setup
+        .client
+        .get_swap_quote_exact_out(a, b, &9_871)
+        .amount_in;
+    assert_eq!(owed, 10_000);
+    assert_eq!(
+        setup.client.flash_swap(
+            &setup.trader,
+            &receiver,
+            a,
+            b,
+            &9_871,
+            &Bytes::new(&setup.env),
+        ),
+        owed
+    );
+    setup.assert_events(&[&events::Swap {
+        from_asset: a.clone(),
+        to_asset: b.clone(),
+        amount_in: 10_000,
+        amount_out: 9_871,
+        fees_paid: 30,
+    }]);
+    assert_eq!(setup.token_a.balance(&receiver), 90_000);
+    assert_eq!(setup.token_b.balance(&receiver), 109_871);
+    assert_eq!(setup.get_pool().reserves_for(a), (1_010_000, 990_129));
+}
+
+#[test]
+fn flash_swap_fails_unless_repaid_in_full() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+    let pool = setup.get_pool();
+    let receiver = setup.receiver(Behavior::Underpay);
+
+    assert_eq!(
+        setup.client.try_flash_swap(
+            &setup.trader,
+            &receiver,
+            a,
+            b,
+            &9_871,
+            &Bytes::new(&setup.env),
+        ),
+        Err(Ok(Error::FlashSwapNotRepaid))
+    );
+    assert_eq!(setup.token_b.balance(&receiver), 100_000);
+    assert_eq!(setup.get_pool(), pool);
+}
+
+#[test]
+fn flash_swap_receivers_cannot_reenter_the_pool() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+    let pool = setup.get_pool();
+    let receiver = setup.receiver(Behavior::Reenter);
+
+    // Soroban rejects the receiver's call back into the router
+    assert!(setup
+        .client
+        .try_flash_swap(
+            &setup.trader,
+            &receiver,
+            a,
+            b,
+            &9_871,
+            &Bytes::new(&setup.env),
+        )
+        .is_err());
+    assert_eq!(setup.get_pool(), pool);
+}
+
+#[test]
+fn swap_routes_through_intermediate_pools() {
+    let setup = Setup::new();
+    let (a, b, c) = (
+        &setup.token_a.address,
+        &setup.token_b.address,
+        &setup.token_c.address,
+    );
+    setup.add_pool(&setup.token_a, &setup.token_c, 1_000_000, 1_000_000);
+    setup.add_pool(&setup.token_c, &setup.token_b, 1_000_000, 1_000_000);
+
+    // 10 000 A buys 9 871 C, which buys 9 841 * 1 000 000 / 1 009 841 of B
+    let swap_path = setup.client.find_optimal_path(a, b, &10_000, &None);
+    assert_eq!(swap_path.path, vec![&setup.env, c.clone()]);
+    assert_eq!(swap_path.min_amount_out, 9_745 * 9_900 / 10_000);
+
+    let quote = setup.client.get_swap_quote(a, b, &10_000);
+    assert_eq!(quote.amount_out, 9_745);
+    assert_eq!(quote.price_impact, 195);
+    assert_eq!(quote.fees_paid, 60);
+
+    assert_eq!(setup.swap(10_000, 9_745), Ok(quote));
+    assert_eq!(setup.token_b.balance(&setup.trader), 1_009_745);
+    assert_eq!(setup.token_c.balance(&setup.trader), 1_000_000);
+    let pool = setup.client.get_pool(c, b);
+    assert_eq!(pool.reserves_for(c), (1_009_871, 990_255));
+}
+
+#[test]
+fn batch_swap_pays_every_leg_with_one_authorization() {
+    let setup = Setup::new();
+    let (a, b, c) = (
+        &setup.token_a.address,
+        &setup.token_b.address,
+        &setup.token_c.address,
+    );
+    setup.add_pool(&setup.token_a, &setup.token_b, 1_000_000, 1_000_000);
+    setup.add_pool(&setup.token_a, &setup.token_c, 1_000_000, 1_000_000);
+
+    let recipients: std::vec::Vec<_> = (0..3).map(|_| Address::generate(&setup.env)).collect();
+    let leg = |to_asset: &Address, recipient: &Address, amount_in, min_amount_out| SwapLeg {
+        from_asset: a.clone(),
+        to_asset: to_asset.clone(),
+        recipient: recipient.clone(),
+        amount_in,
+        min_amount_out,
+        quoted_amount_out: 0,
+        slippage_bps: None,
+    };
+    let legs = vec![
+        &setup.env,
+        leg(b, &recipients[0], 10_000, 9_871),
+        leg(c, &recipients[1], 10_000, 9_871),
+        leg(a, &recipients[2], 5_000, 5_000),
+    ];
+
+    let results = setup
+        .client
+        .execute_batch_swap(&setup.trader, &legs, &setup.deadline());
+    assert_eq!(setup.env.auths().len(), 1);
+    assert_eq!(setup.env.auths()[0].0, setup.trader);
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| r.amount_out)
+            .collect::<std::vec::Vec<_>>(),
+        [9_871, 9_871, 5_000]
+    );
+    assert_eq!(setup.token_b.balance(&recipients[0]), 9_871);
+    assert_eq!(setup.token_c.balance(&recipients[1]), 9_871);
+    assert_eq!(setup.token_a.balance(&recipients[2]), 5_000);
+    assert_eq!(setup.token_a.balance(&setup.trader), 975_000);
+}
+
+#[test]
+fn batch_swap_reverts_every_leg_if_one_fails() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+    let pool = setup.get_pool();
+
+    let recipient = Address::generate(&setup.env);
+    let leg = |min_amount_out| SwapLeg {
+        from_asset: a.clone(),
+        to_asset: b.clone(),
+        recipient: recipient.clone(),
+        amount_in: 10_000,
+        min_amount_out,
+        quoted_amount_out: 0,
+        slippage_bps: None,
+    };
+
+    // The second leg prices off the reserves the first left behind
+    assert_eq!(
+        setup.client.try_execute_batch_swap(
+            &setup.trader,
+            &vec![&setup.env, leg(9_871), leg(9_871)],
+            &setup.deadline(),
+        ),
+        Err(Ok(Error::SlippageExceeded))
+    );
+    assert_eq!(setup.token_b.balance(&recipient), 0);
+    assert_eq!(setup.token_a.balance(&setup.trader), 1_000_000);
+    assert_eq!(setup.get_pool(), pool);
+}
+
+#[test]
+fn swap_takes_the_route_with_the_best_output() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(10_000, 10_000);
+    setup.add_pool(&setup.token_a, &setup.token_c, 1_000_000, 1_000_000);
+    setup.add_pool(&setup.token_c, &setup.token_b, 1_000_000, 1_000_000);
+
+    // The deep two-hop route beats the shallow direct pool for large trades...
+    assert_eq!(
+        setup
+            .client
+            .find_optimal_path(a, b, &10_000, &None)
+            .path
+            .len(),
+        1
+    );
+    // ...but not for small ones, where the extra fee dominates
+    assert!(setup
+        .client
+        .find_optimal_path(a, b, &10, &None)
+        .path
+        .is_empty());
+
+    // Limited to one hop, only the direct pool is searched
+    setup.client.set_max_hops(&1);
+    assert!(setup
+        .client
+        .find_optimal_path(a, b, &10_000, &None)
+        .path
+        .is_empty());
+    assert_eq!(setup.client.get_swap_quote(a, b, &10_000).amount_out, 4_992);
+}
+
+#[test]
+fn path_search_respects_max_hops() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.add_pool(&setup.token_a, &setup.token_c, 1_000_000, 1_000_000);
+    setup.add_pool(&setup.token_c, &setup.token_b, 1_000_000, 1_000_000);
+
+    assert_eq!(setup.client.get_max_hops(), 3);
+    assert_eq!(
+        setup.client.try_set_max_hops(&0),
+        Err(Ok(Error::InvalidPath))
+    );
+    assert_eq!(
+        setup.client.try_set_max_hops(&(MAX_HOPS_LIMIT + 1)),
+        Err(Ok(Error::InvalidPath))
+    );
+
+    setup.client.set_max_hops(&1);
+    assert_eq!(setup.swap(10_000, 0), Err(Error::PoolNotFound));
+    assert_eq!(
+        setup.client.try_find_optimal_path(a, b, &10_000, &None),
+        Err(Ok(Error::PoolNotFound))
+    );
+}
+
+#[test]
+fn exact_out_swap_takes_only_the_input_needed() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+
+    // The reverse of `swap_prices_off_reserves`: 9 871 of B needs 10 000 of A
+    let expected = SwapExactOutResult {
+        amount_in: 10_000,
+        price_impact: 98,
+        fees_paid: 30,
+    };
+    assert_eq!(
+        setup.client.get_swap_quote_exact_out(a, b, &9_871),
+        expected
+    );
+
+    let recipient = Address::generate(&setup.env);
+    assert_eq!(
+        setup.client.try_execute_swap_exact_out(
+            a,
+            b,
+            &9_871,
+            &9_999,
+            &setup.trader,
+            &recipient,
+            &setup.deadline()
+        ),
+        Err(Ok(Error::SlippageExceeded))
+    );
+    assert_eq!(
+        setup.client.execute_swap_exact_out(
+            a,
+            b,
+            &9_871,
+            &20_000,
+            &setup.trader,
+            &recipient,
+            &setup.deadline()
+        ),
+        expected
+    );
+    assert_eq!(setup.token_a.balance(&setup.trader), 990_000);
+    assert_eq!(setup.token_b.balance(&recipient), 9_871);
+    assert_eq!(setup.token_a.balance(&setup.client.address), 1_010_000);
+}
+
+#[test]
+fn exact_out_swap_routes_through_intermediate_pools() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.add_pool(&setup.token_a, &setup.token_c, 1_000_000, 1_000_000);
+    setup.add_pool(&setup.token_c, &setup.token_b, 1_000_000, 1_000_000);
+
+    // The reverse of `swap_routes_through_intermediate_pools`
+    let quote = setup.client.get_swap_quote_exact_out(a, b, &9_745);
+    assert_eq!(quote.amount_in, 10_000);
+    assert_eq!(quote.fees_paid, 60);
+
+    let recipient = Address::generate(&setup.env);
+    setup.client.execute_swap_exact_out(
+        a,
+        b,
+        &9_745,
+        &10_000,
+        &setup.trader,
+        &recipient,
+        &setup.deadline(),
+    );
+    assert_eq!(setup.token_a.balance(&setup.trader), 990_000);
+    assert_eq!(setup.token_c.balance(&setup.trader), 1_000_000);
+    assert_eq!(setup.token_b.balance(&recipient), 9_745);
+
+    // Nothing is left behind in the router beyond the pools' reserves
+    let (reserve_a, reserve_c) = setup
+        .client
+        .get_pool(a, &setup.token_c.address)
+        .reserves_for(a);
+    assert_eq!((reserve_a, reserve_c), (1_010_000, 990_129));
+    assert_eq!(setup.token_c.balance(&setup.client.address), 2_000_000);
+}
+
+#[test]
+fn exact_out_swap_fails_without_liquidity() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+
+    assert_eq!(
+        setup.client.try_get_swap_quote_exact_out(a, b, &1_000),
+        Err(Ok(Error::PoolNotFound))
+    );
+
+    setup.pool(1_000_000, 1_000_000);
+    assert_eq!(
+        setup.client.try_get_swap_quote_exact_out(a, b, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        setup.client.try_get_swap_quote_exact_out(a, b, &1_000_000),
+        Err(Ok(Error::InsufficientLiquidity))
+    );
+}
+
+#[test]
+fn swaps_and_quotes_are_limited_to_supported_assets() {
+    let setup = Setup::new();
+    let (a, b, c) = (
+        &setup.token_a.address,
+        &setup.token_b.address,
+        &setup.token_c.address,
+    );
+    setup.add_pool(&setup.token_a, &setup.token_c, 1_000_000, 1_000_000);
+    setup.add_pool(&setup.token_c, &setup.token_b, 1_000_000, 1_000_000);
+    assert_eq!(
+        setup.client.list_supported_assets(),
+        vec![&setup.env, a.clone(), b.clone(), c.clone()]
+    );
+
+    // Delisting the intermediate asset closes the only route between A and B
+    setup.client.remove_supported_asset(c);
+    setup.assert_events(&[&events::AssetUnsupported { asset: c.clone() }]);
+    assert!(!setup.client.is_asset_supported(c));
+    assert_eq!(
+        setup.client.list_supported_assets(),
+        vec![&setup.env, a.clone(), b.clone()]
+    );
+    assert_eq!(setup.swap(10_000, 0), Err(Error::PoolNotFound));
+
+    // Swaps and quotes in or out of it fail outright
+    assert_eq!(
+        setup.client.try_get_swap_quote(a, c, &10_000),
+        Err(Ok(Error::UnauthorizedAsset))
+    );
+    assert_eq!(
+        setup.client.try_get_swap_quote_exact_out(c, a, &10_000),
+        Err(Ok(Error::UnauthorizedAsset))
+    );
+    assert_eq!(
+        setup.client.try_find_optimal_path(a, c, &10_000, &None),
+        Err(Ok(Error::UnauthorizedAsset))
+    );
+    assert_eq!(
+        setup.client.try_execute_swap(
+            c,
+            c,
+            &10_000,
+            &0,
+            &0,
+            &None,
+            &setup.trader,
+            &setup.trader,
+            &setup.deadline()
+        ),
+        Err(Ok(Error::UnauthorizedAsset))
+    );
+    assert_eq!(
+        setup.client.try_execute_swap_exact_out(
+            a,
+            c,
+            &100,
+            &10_000,
+            &setup.trader,
+            &setup.trader,
+            &setup.deadline()
+        ),
+        Err(Ok(Error::UnauthorizedAsset))
+    );
+
+    // Liquidity in the delisted asset can still be withdrawn
+    let shares = setup.client.get_shares(a, c, &setup.lp);
+    setup
+        .client
+        .remove_liquidity(&setup.lp, a, c, &shares, &0, &0);
+
+    // Listing it again reopens the route; adding and removing are idempotent
+    setup.client.add_supported_asset(c);
+    setup.assert_events(&[&events::AssetSupported { asset: c.clone() }]);
+    setup.client.add_supported_asset(c);
+    setup.assert_events(&[]);
+    assert_eq!(setup.client.list_supported_assets().len(), 3);
+    assert!(setup
+        .client
+        .find_optimal_path(c, b, &10_000, &None)
+        .path
+        .is_empty());
+
+    setup.client.remove_supported_asset(a);
+    setup.client.remove_supported_asset(a);
+    assert_eq!(
+        setup.client.list_supported_assets(),
+        vec![&setup.env, b.clone(), c.clone()]
+    );
+}
+
+#[test]
+#[should_panic]
+fn only_the_admin_can_change_supported_assets() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let client = MultiAssetRouterClient::new(&env, &env.register(MultiAssetRouter, (admin,)));
+    client.remove_supported_asset(&Address::generate(&env));
+}
+
+#[test]
+fn swaps_fail_after_their_deadline() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+    let deadline = setup.deadline();
+
+    setup.env.ledger().with_mut(|l| l.timestamp = deadline);
+    assert!(setup.swap(10_000, 0).is_ok());
+
+    setup.env.ledger().with_mut(|l| l.timestamp = deadline + 1);
+    assert_eq!(
+        setup.client.try_execute_swap(
+            a,
+            b,
+            &10_000,
+            &0,
+            &0,
+            &None,
+            &setup.trader,
+            &setup.trader,
+            &deadline
+        ),
+        Err(Ok(Error::DeadlinePassed))
+    );
+    assert_eq!(
+        setup.client.try_execute_swap_exact_out(
+            a,
+            b,
+            &1_000,
+            &10_000,
+            &setup.trader,
+            &setup.trader,
+            &deadline
+        ),
+        Err(Ok(Error::DeadlinePassed))
+    );
+    assert_eq!(
+        setup.client.try_execute_swap(
+            a,
+            a,
+            &10_000,
+            &0,
+            &0,
+            &None,
+            &setup.trader,
+            &setup.trader,
+            &deadline
+        ),
+        Err(Ok(Error::DeadlinePassed))
+    );
+}
+
+#[test]
+fn optimal_path_allows_for_the_callers_slippage() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+
+    // 10 000 of A quotes 9 871 of B; the default tolerance is 1%
+    let min_amount_out = |slippage_bps| {
+        setup
+            .client
+            .find_optimal_path(a, b, &10_000, &slippage_bps)
+            .min_amount_out
+    };
+    assert_eq!(min_amount_out(None), 9_871 * 9_900 / 10_000);
+    assert_eq!(min_amount_out(Some(0)), 9_871);
+    assert_eq!(min_amount_out(Some(50)), 9_871 * 9_950 / 10_000);
+    assert_eq!(min_amount_out(Some(1_000)), 9_871 * 9_000 / 10_000);
+    assert_eq!(
+        setup
+            .client
+            .try_find_optimal_path(a, b, &10_000, &Some(1_001)),
+        Err(Ok(Error::InvalidSlippage))
+    );
+    assert_eq!(
+        setup.client.try_find_optimal_path(a, b, &10_000, &Some(-1)),
+        Err(Ok(Error::InvalidSlippage))
+    );
+
+    // The admin can raise the maximum, but not below the default tolerance
+    setup.client.set_max_slippage(&2_000);
+    assert_eq!(setup.client.get_max_slippage(), 2_000);
+    assert_eq!(min_amount_out(Some(2_000)), 9_871 * 8_000 / 10_000);
+    assert_eq!(
+        setup.client.try_set_max_slippage(&99),
+        Err(Ok(Error::InvalidSlippage))
+    );
+    assert_eq!(
+        setup.client.try_set_max_slippage(&10_001),
+        Err(Ok(Error::InvalidSlippage))
+    );
+
+    setup.client.set_slippage_tolerance(&2_000);
+    assert_eq!(min_amount_out(None), 9_871 * 8_000 / 10_000);
+    assert_eq!(
+        setup.client.try_set_slippage_tolerance(&2_001),
+        Err(Ok(Error::InvalidSlippage))
+    );
+}
+
+#[test]
+fn swaps_are_limited_to_the_maximum_slippage() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+    let quoted = setup.client.get_swap_quote(a, b, &10_000).amount_out;
+
+    // Callers who set no minimum output are still held to their quote
+    let swap = |to_asset, slippage_bps| {
+        setup.client.try_execute_swap(
+            a,
+            to_asset,
+            &10_000,
+            &0,
+            &quoted,
+            &slippage_bps,
+            &setup.trader,
+            &setup.trader,
+            &setup.deadline(),
+        )
+    };
+    let leg = |slippage_bps| SwapLeg {
+        from_asset: a.clone(),
+        to_asset: b.clone(),
+        recipient: setup.trader.clone(),
+        amount_in: 10_000,
+        min_amount_out: 0,
+        quoted_amount_out: quoted,
+        slippage_bps,
+    };
+    let batch_swap = |slippage_bps| {
+        setup.client.try_execute_batch_swap(
+            &setup.trader,
+            &vec![&setup.env, leg(slippage_bps)],
+            &setup.deadline(),
+        )
+    };
+    assert_eq!(swap(b, Some(1_001)), Err(Ok(Error::InvalidSlippage)));
+    assert_eq!(swap(a, Some(1_001)), Err(Ok(Error::InvalidSlippage)));
+    assert_eq!(batch_swap(Some(-1)), Err(Ok(Error::InvalidSlippage)));
+    assert_eq!(setup.token_a.balance(&setup.trader), 1_000_000);
+
+    assert_eq!(swap(b, Some(0)).unwrap().unwrap().amount_out, 9_871);
+    // The first swap moved the price, so the same quote now buys 9 678,
+    // more than 1% short of it but within 3%
+    assert_eq!(swap(b, None), Err(Ok(Error::SlippageExceeded)));
+    assert_eq!(swap(b, Some(300)).unwrap().unwrap().amount_out, 9_678);
+
+    // A trade landing first can't push the output further below the quote
+    // than the maximum slippage
+    setup.client.execute_swap(
+        a,
+        b,
+        &500_000,
+        &0,
+        &0,
+        &None,
+        &setup.lp,
+        &setup.lp,
+        &setup.deadline(),
+    );
+    assert_eq!(swap(b, Some(1_000)), Err(Ok(Error::SlippageExceeded)));
+    assert_eq!(batch_swap(Some(1_000)), Err(Ok(Error::SlippageExceeded)));
+    assert_eq!(
+        setup.client.try_execute_swap(
+            a,
+            b,
+            &10_000,
+            &0,
+            &-1,
+            &None,
+            &setup.trader,
+            &setup.trader,
+            &setup.deadline(),
+        ),
+        Err(Ok(Error::InvalidAmount))
+    );
+}
+
+#[test]
+fn swaps_are_checked_against_the_pair_oracle() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    setup.pool(1_000_000, 1_000_000);
+
+    let oracle = setup.env.register(MockOracle, ());
+    let oracle_client = MockOracleClient::new(&setup.env, &oracle);
+    oracle_client.set_price(a, &10_000_000);
+    oracle_client.set_price(b, &10_000_000);
+
+    setup.client.set_pair_oracle(b, a, &oracle, &200, &300);
+    let config = OracleConfig {
+        oracle: oracle.clone(),
+        max_deviation_bps: 200,
+        max_age_seconds: 300,
+    };
+    assert_eq!(setup.client.get_pair_oracle(a, b), Some(config));
+    assert_eq!(
+        setup
+            .client
+            .try_set_pair_oracle(a, b, &oracle, &10_001, &300),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        setup.client.try_set_pair_oracle(a, b, &oracle, &200, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    // 9 871 for 10 000 is 1.29% off the oracle's 1:1, inside the 2% allowed
+    assert!(setup.swap(10_000, 0).is_ok());
+    // A larger trade moves the pool price too far
+    assert_eq!(setup.swap(100_000, 0), Err(Error::PriceDeviation));
+
+    // So does a pool price that strays from the oracle's
+    oracle_client.set_price(b, &20_000_000);
+    assert_eq!(setup.swap(1_000, 0), Err(Error::PriceDeviation));
+    assert_eq!(
+        setup.client.try_execute_swap_exact_out(
+            a,
+            b,
+            &1_000,
+            &10_000,
+            &setup.trader,
+            &setup.trader,
+            &setup.deadline()
+        ),
+        Err(Ok(Error::PriceDeviation))
+    );
+
+    // Without an oracle the pool price alone decides
+    setup.client.remove_pair_oracle(a, b);
+    assert_eq!(setup.client.get_pair_oracle(a, b), None);
+    assert!(setup.swap(100_000, 0).is_ok());
+}
+
+#[test]
+fn swaps_fail_without_an_oracle_price() {
+    let setup = Setup::new();
+    let (a, b, c) = (
+        &setup.token_a.address,
+        &setup.token_b.address,
+        &setup.token_c.address,
+    );
+    setup.add_pool(&setup.token_a, &setup.token_c, 1_000_000, 1_000_000);
+    setup.add_pool(&setup.token_c, &setup.token_b, 1_000_000, 1_000_000);
+
+    // Only the C/B hop of the route has an oracle, and it has no price for B
+    let oracle = setup.env.register(MockOracle, ());
+    let oracle_client = MockOracleClient::new(&setup.env, &oracle);
+    oracle_client.set_price(c, &10_000_000);
+    setup.client.set_pair_oracle(c, b, &oracle, &500, &300);
+    assert_eq!(setup.swap(10_000, 0), Err(Error::OraclePriceUnavailable));
+
+    oracle_client.set_price(b, &10_000_000);
+    assert_eq!(setup.swap(10_000, 0).unwrap().amount_out, 9_745);
+    assert_eq!(setup.client.get_pair_oracle(a, c), None);
+
+    // Prices older than the maximum age count as missing
+    let set_at = setup.env.ledger().timestamp();
+    setup.env.ledger().with_mut(|l| l.timestamp = set_at + 301);
+    assert_eq!(setup.swap(10_000, 0), Err(Error::OraclePriceUnavailable));
+
+    oracle_client.set_price(c, &10_000_000);
+    assert_eq!(setup.swap(10_000, 0), Err(Error::OraclePriceUnavailable));
+    oracle_client.set_price(b, &10_000_000);
+    assert!(setup.swap(10_000, 0).is_ok());
+}
+
+#[test]
+#[should_panic]
+fn only_the_admin_can_set_a_pair_oracle() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let client = MultiAssetRouterClient::new(&env, &env.register(MultiAssetRouter, (admin,)));
+    let token = Address::generate(&env);
+    client.set_pair_oracle(&token, &Address::generate(&env), &token, &100, &300);
+}
+
+#[test]
+fn twap_averages_pool_prices_over_time() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    let at = |timestamp| setup.env.ledger().with_mut(|l| l.timestamp = timestamp);
+
+    at(1_000);
+    setup.pool(1_000_000, 1_000_000);
+    at(1_600);
+    setup.swap(10_000, 9_871).unwrap();
+    at(2_200);
+
+    // The price held at 1:1 for the first 600 seconds, then moved with the swap
+    let price_a = 990_129 * PRICE_SCALE / 1_010_000;
+    let price_b = 1_010_000 * PRICE_SCALE / 990_129;
+    assert_eq!(setup.client.get_observations(a, b).len(), 2);
+    assert_eq!(
+        setup.client.get_twap(a, b, &1_200),
+        (PRICE_SCALE + price_a) / 2
+    );
+    assert_eq!(setup.client.get_twap(a, b, &600), price_a);
+    assert_eq!(setup.client.get_twap(b, a, &600), price_b);
+
+    // A swap only counts once time has passed at its price
+    setup.swap(500_000, 0).unwrap();
+    assert_eq!(setup.client.get_twap(a, b, &600), price_a);
+
+    assert_eq!(
+        setup.client.try_get_twap(a, b, &1_201),
+        Err(Ok(Error::TwapUnavailable))
+    );
+    assert_eq!(
+        setup.client.try_get_twap(a, b, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+}
+
+#[test]
+fn prices_too_large_to_scale_dont_block_the_pool() {
+    let setup = Setup::new();
+    let (a, b) = (&setup.token_a.address, &setup.token_b.address);
+    let at = |timestamp| setup.env.ledger().with_mut(|l| l.timestamp = timestamp);
+    let (reserve_a, reserve_b) = (10_000_000_000_000, 10_000_000_000_000_000_000_000_000);
+    setup.mint(&setup.token_a, &setup.lp, reserve_a);
+    setup.mint(&setup.token_b, &setup.lp, reserve_b);
+
+    // B per A scaled by PRICE_SCALE is past i128::MAX, so only A per B has a TWAP
+    at(1_000);
+    let (_, _, shares) = setup.pool(reserve_a, reserve_b);
+    at(1_600);
+    assert!(setup.swap(10_000, 0).is_ok());
+    at(2_200);
+
+    assert_eq!(
+        setup.client.get_twap(b, a, &600),
+        setup.get_pool().reserve_a * PRICE_SCALE / setup.get_pool().reserve_b
+    );
+    assert_eq!(
+        setup.client.try_get_twap(a, b, &600),
+        Err(Ok(Error::TwapUnavailable))
+    );
+    let withdrawn = 1_000_000_000_000;
+    setup
+        .client
+        .remove_liquidity(&setup.lp, a, b, &withdrawn, &0, &0);
+    assert_eq!(
+        setup.get_pool().total_shares,
+        shares + MINIMUM_LIQUIDITY - withdrawn
+    );
+}
@@ -0,0 +1,183 @@
+use soroban_sdk::{contractimpl, contracttype, Address, Env, Vec};
+
+use crate::{
+    pool::{sort_pair, MAX_BUMP},
+    DataKey, Error, MultiAssetRouter, MultiAssetRouterArgs, MultiAssetRouterClient, Pool,
+};
+
+/// Fixed-point scale of pool prices and their cumulative sums
+pub const PRICE_SCALE: i128 = 100_000_000_000_000;
+
+/// Minimum seconds between stored observations of a pool's cumulative prices
+pub const OBSERVATION_PERIOD: u64 = 600;
+
+/// Observations kept per pool, dropping the oldest first, so TWAP windows can
+/// reach back about a day
+pub const MAX_OBSERVATIONS: u32 = 144;
+
+/// A pool's cumulative prices at the first update in an observation period
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Observation {
+    pub timestamp: u64,
+    pub price_a_cumulative: i128,
+    pub price_b_cumulative: i128,
+    pub price_a_skipped: u64,
+    pub price_b_skipped: u64,
+}
+
+impl Pool {
+    /// The pool's cumulative prices as of `timestamp`, adding the spot prices of
+    /// `token_a` and `token_b` for every second since the last update.
+    ///
+    /// As in Uniswap v2, the sums are meant to wrap around on overflow: only
+    /// the difference between two readings is meaningful. A price too large to
+    /// scale is left out of its sum, and its seconds counted as skipped instead,
+    /// rather than failing the swap or liquidity change that brings the sums up
+    /// to date.
+    pub fn observe(&self, timestamp: u64) -> Observation {
+        let mut observation = Observation {
+            timestamp,
+            price_a_cumulative: self.price_a_cumulative,
+            price_b_cumulative: self.price_b_cumulative,
+            price_a_skipped: self.price_a_skipped,
+            price_b_skipped: self.price_b_skipped,
+        };
+        let elapsed = timestamp.saturating_sub(self.last_updated);
+        if elapsed == 0 || self.reserve_a == 0 || self.reserve_b == 0 {
+            return observation;
+        }
+
+        accumulate(
+            &mut observation.price_a_cumulative,
+            &mut observation.price_a_skipped,
+            self.reserve_a,
+            self.reserve_b,
+            elapsed,
+        );
+        accumulate(
+            &mut observation.price_b_cumulative,
+            &mut observation.price_b_skipped,
+            self.reserve_b,
+            self.reserve_a,
+            elapsed,
+        );
+        observation
+    }
+}
+
+/// Add `elapsed` seconds of the price `reserve_out / reserve_in` to
+/// `cumulative`, or count them in `skipped` if the price is too large to scale
+fn accumulate(
+    cumulative: &mut i128,
+    skipped: &mut u64,
+    reserve_in: i128,
+    reserve_out: i128,
+    elapsed: u64,
+) {
+    match reserve_out.checked_mul(PRICE_SCALE) {
+        Some(scaled) => {
+            *cumulative =
+                cumulative.wrapping_add((scaled / reserve_in).wrapping_mul(elapsed as i128))
+        }
+        None => *skipped += elapsed,
+    }
+}
+
+#[contractimpl]
+impl MultiAssetRouter {
+    /// Get the time-weighted average price of `token_a` in `token_b`, scaled by
+    /// `PRICE_SCALE`, over at least the last `window_seconds`.
+    ///
+    /// The average runs from the latest stored observation at least
+    /// `window_seconds` old, so it may cover up to `OBSERVATION_PERIOD` more, or
+    /// longer if the pool hasn't been traded since. Fails with `TwapUnavailable`
+    /// if the pool has no observation that old, or the price was too large to
+    /// track for any of that time.
+    pub fn get_twap(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+        window_seconds: u64,
+    ) -> Result<i128, Error> {
+        if window_seconds == 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let pool = Self::get_pool(env.clone(), token_a.clone(), token_b)?;
+
+        let now = env.ledger().timestamp();
+        let start = now
+            .checked_sub(window_seconds)
+            .ok_or(Error::TwapUnavailable)?;
+        let oldest =
+            Self::get_observations(env.clone(), pool.token_a.clone(), pool.token_b.clone())
+                .iter()
+                .rev()
+                .find(|observation| observation.timestamp <= start)
+                .ok_or(Error::TwapUnavailable)?;
+
+        let latest = pool.observe(now);
+        let (cumulative, cumulative_then, skipped, skipped_then) = if token_a == pool.token_a {
+            (
+                latest.price_a_cumulative,
+                oldest.price_a_cumulative,
+                latest.price_a_skipped,
+                oldest.price_a_skipped,
+            )
+        } else {
+            (
+                latest.price_b_cumulative,
+                oldest.price_b_cumulative,
+                latest.price_b_skipped,
+                oldest.price_b_skipped,
+            )
+        };
+        if skipped != skipped_then {
+            return Err(Error::TwapUnavailable);
+        }
+        Ok(cumulative.wrapping_sub(cumulative_then) / (now - oldest.timestamp) as i128)
+    }
+
+    /// Get the stored observations of a pool's cumulative prices, oldest first
+    pub fn get_observations(env: Env, token_a: Address, token_b: Address) -> Vec<Observation> {
+        let (token_a, token_b) = sort_pair(token_a, token_b);
+        env.storage()
+            .persistent()
+            .get(&DataKey::Observations(token_a, token_b))
+            .unwrap_or(Vec::new(&env))
+    }
+}
+
+impl MultiAssetRouter {
+    /// Bring `pool`'s cumulative prices up to the current ledger timestamp,
+    /// before its reserves change, and record them if this is the first update
+    /// in the observation period
+    pub(crate) fn update_cumulative_prices(env: &Env, pool: &mut Pool) {
+        let now = env.ledger().timestamp();
+        let observation = pool.observe(now);
+        pool.price_a_cumulative = observation.price_a_cumulative;
+        pool.price_b_cumulative = observation.price_b_cumulative;
+        pool.price_a_skipped = observation.price_a_skipped;
+        pool.price_b_skipped = observation.price_b_skipped;
+        pool.last_updated = now;
+
+        let mut observations =
+            Self::get_observations(env.clone(), pool.token_a.clone(), pool.token_b.clone());
+        if observations
+            .last()
+            .is_some_and(|last| last.timestamp / OBSERVATION_PERIOD == now / OBSERVATION_PERIOD)
+        {
+            return;
+        }
+
+        observations.push_back(observation);
+        if observations.len() > MAX_OBSERVATIONS {
+            observations.pop_front();
+        }
+        let key = DataKey::Observations(pool.token_a.clone(), pool.token_b.clone());
+        env.storage().persistent().set(&key, &observations);
+        env.storage()
+            .persistent()
+            .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+    }
+}
@@ -0,0 +1,146 @@
+//! SEP-41 token for liquidity provider shares of a `multi_asset_router` pool.
+//!
+//! The router deploys one per pool as its admin, mints shares when liquidity is
+//! added and burns them when it is removed. Holders can burn their own shares,
+//! giving up their claim on the pool, but only the admin can burn someone
+//! else's; shares are otherwise a plain token.
+#![no_std]
+use soroban_sdk::{contract, contractimpl, token, Address, Env, MuxedAddress, String};
+
+pub mod events;
+mod storage;
+
+use storage::{
+    bump_instance, read_administrator, read_allowance, read_balance, read_metadata,
+    receive_balance, spend_allowance, spend_balance, write_administrator, write_allowance,
+    write_metadata, Metadata,
+};
+
+fn check_nonnegative_amount(amount: i128) {
+    if amount < 0 {
+        panic!("negative amount is not allowed: {}", amount)
+    }
+}
+
+#[contract]
+pub struct ShareToken;
+
+#[contractimpl]
+impl ShareToken {
+    pub fn __constructor(e: Env, admin: Address, decimal: u32, name: String, symbol: String) {
+        if decimal > 18 {
+            panic!("Decimal must not be greater than 18");
+        }
+        write_administrator(&e, &admin);
+        write_metadata(
+            &e,
+            &Metadata {
+                decimal,
+                name,
+                symbol,
+            },
+        );
+    }
+
+    /// Mint `amount` shares to `to` (admin only)
+    pub fn mint(e: Env, to: Address, amount: i128) {
+        check_nonnegative_amount(amount);
+        read_administrator(&e).require_auth();
+        bump_instance(&e);
+
+        receive_balance(&e, to.clone(), amount);
+        events::Mint { to, amount }.publish(&e);
+    }
+
+    /// Get the admin allowed to mint shares
+    pub fn admin(e: Env) -> Address {
+        read_administrator(&e)
+    }
+}
+
+#[contractimpl]
+impl token::TokenInterface for ShareToken {
+    fn allowance(e: Env, from: Address, spender: Address) -> i128 {
+        bump_instance(&e);
+        read_allowance(&e, from, spender).amount
+    }
+
+    fn approve(e: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        from.require_auth();
+        check_nonnegative_amount(amount);
+        bump_instance(&e);
+
+        write_allowance(&e, from.clone(), spender.clone(), amount, expiration_ledger);
+        events::Approve {
+            from,
+            spender,
+            amount,
+            expiration_ledger,
+        }
+        .publish(&e);
+    }
+
+    fn balance(e: Env, id: Address) -> i128 {
+        bump_instance(&e);
+        read_balance(&e, id)
+    }
+
+    fn transfer(e: Env, from: Address, to: MuxedAddress, amount: i128) {
+        from.require_auth();
+        check_nonnegative_amount(amount);
+        bump_instance(&e);
+
+        let to = to.address();
+        spend_balance(&e, from.clone(), amount);
+        receive_balance(&e, to.clone(), amount);
+        events::Transfer { from, to, amount }.publish(&e);
+    }
+
+    fn transfer_from(e: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+        check_nonnegative_amount(amount);
+        bump_instance(&e);
+
+        spend_allowance(&e, from.clone(), spender, amount);
+        spend_balance(&e, from.clone(), amount);
+        receive_balance(&e, to.clone(), amount);
+        events::Transfer { from, to, amount }.publish(&e);
+    }
+
+    fn burn(e: Env, from: Address, amount: i128) {
+        from.require_auth();
+        check_nonnegative_amount(amount);
+        bump_instance(&e);
+
+        spend_balance(&e, from.clone(), amount);
+        events::Burn { from, amount }.publish(&e);
+    }
+
+    fn burn_from(e: Env, spender: Address, from: Address, amount: i128) {
+        if spender != read_administrator(&e) {
+            panic!("only the admin can burn");
+        }
+        spender.require_auth();
+        check_nonnegative_amount(amount);
+        bump_instance(&e);
+
+        spend_allowance(&e, from.clone(), spender, amount);
+        spend_balance(&e, from.clone(), amount);
+        events::Burn { from, amount }.publish(&e);
+    }
+
+    fn decimals(e: Env) -> u32 {
+        read_metadata(&e).decimal
+    }
+
+    fn name(e: Env) -> String {
+        read_metadata(&e).name
+    }
+
+    fn symbol(e: Env) -> String {
+        read_metadata(&e).symbol
+    }
+}
+
+#[cfg(test)]
+mod test;
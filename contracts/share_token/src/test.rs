@@ -0,0 +1,93 @@
+#![cfg(test)]
+extern crate std;
+
+use soroban_sdk::{testutils::Address as _, Address, Env, String};
+
+use crate::{ShareToken, ShareTokenClient};
+
+fn create_token<'a>(env: &Env, admin: &Address) -> ShareTokenClient<'a> {
+    let address = env.register(
+        ShareToken,
+        (
+            admin,
+            7u32,
+            String::from_str(env, "Pool Share"),
+            String::from_str(env, "POOL"),
+        ),
+    );
+    ShareTokenClient::new(env, &address)
+}
+
+#[test]
+fn mint_transfer_and_burn() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let token = create_token(&env, &admin);
+    assert_eq!(token.admin(), admin);
+    assert_eq!(token.decimals(), 7);
+    assert_eq!(token.symbol(), String::from_str(&env, "POOL"));
+
+    token.mint(&alice, &1_000);
+    assert_eq!(token.balance(&alice), 1_000);
+
+    token.transfer(&alice, &bob, &400);
+    assert_eq!(token.balance(&alice), 600);
+    assert_eq!(token.balance(&bob), 400);
+
+    token.approve(&bob, &admin, &300, &200);
+    assert_eq!(token.allowance(&bob, &admin), 300);
+    token.burn_from(&admin, &bob, &100);
+    assert_eq!(token.balance(&bob), 300);
+    assert_eq!(token.allowance(&bob, &admin), 200);
+
+    token.burn(&alice, &600);
+    assert_eq!(env.auths()[0].0, alice);
+    assert_eq!(token.balance(&alice), 0);
+}
+
+#[test]
+#[should_panic(expected = "insufficient balance")]
+fn transfer_more_than_balance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let token = create_token(&env, &admin);
+
+    token.mint(&alice, &100);
+    token.transfer(&alice, &bob, &101);
+}
+
+#[test]
+#[should_panic]
+fn only_the_admin_can_mint() {
+    let env = Env::default();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let token = create_token(&env, &admin);
+
+    token.mint(&alice, &100);
+}
+
+#[test]
+#[should_panic(expected = "only the admin can burn")]
+fn only_the_admin_can_burn() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let token = create_token(&env, &admin);
+
+    token.mint(&alice, &100);
+    token.approve(&alice, &bob, &100, &200);
+    token.burn_from(&bob, &alice, &100);
+}
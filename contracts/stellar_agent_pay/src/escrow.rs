@@ -0,0 +1,220 @@
+use soroban_sdk::{contractimpl, contracttype, token, Address, Env, String};
+
+use crate::{
+    events, storage, DataKey, Error, Invoice, InvoiceExpiry, StellarAgentPayContract,
+    StellarAgentPayContractArgs, StellarAgentPayContractClient,
+};
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EscrowStatus {
+    /// Invoice has not been paid yet
+    Unfunded,
+    /// Payment is held by the contract
+    Held,
+    /// Payer disputed the payment; only the arbiter can settle it
+    Disputed,
+    /// Payment was released to the recipient
+    Released,
+    /// Arbiter split the payment between payer and recipient
+    Resolved,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Escrow {
+    pub arbiter: Address,
+    /// Seconds after payment until anyone may release the funds to the recipient
+    pub release_timeout: u64,
+    pub release_at: Option<u64>,
+    pub status: EscrowStatus,
+}
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// Create an invoice whose payment is held by the contract until the payer or
+    /// `arbiter` releases it, or `release_timeout` seconds pass after payment.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_escrow_invoice(
+        env: Env,
+        amount: i128,
+        currency: Address,
+        recipient: Address,
+        description: String,
+        expiry: Option<InvoiceExpiry>,
+        arbiter: Address,
+        release_timeout: u64,
+    ) -> Result<u64, Error> {
+        let escrow = Escrow {
+            arbiter,
+            release_timeout,
+            release_at: None,
+            status: EscrowStatus::Unfunded,
+        };
+        Self::new_invoice(
+            &env,
+            amount,
+            currency,
+            recipient,
+            description,
+            expiry,
+            Some(escrow),
+        )
+    }
+
+    /// Release held funds to the recipient, less the platform fee. The payer and
+    /// arbiter may release at any time; once the release timeout has passed anyone may.
+    pub fn release(env: Env, invoice_id: u64, caller: Address) -> Result<bool, Error> {
+        caller.require_auth();
+
+        let (invoice, mut escrow) = Self::escrow_invoice(&env, invoice_id)?;
+        if escrow.status != EscrowStatus::Held {
+            return Err(Error::InvalidEscrowState);
+        }
+
+        let now = env.ledger().timestamp();
+        let timed_out = escrow
+            .release_at
+            .is_some_and(|release_at| now >= release_at);
+        if !timed_out && Some(&caller) != invoice.payer.as_ref() && caller != escrow.arbiter {
+            return Err(Error::Unauthorized);
+        }
+
+        let remaining = Self::pay_out_held(&env, &invoice);
+
+        escrow.status = EscrowStatus::Released;
+        storage::save_escrow(&env, invoice_id, &escrow);
+
+        events::EscrowReleased {
+            invoice_id,
+            recipient: invoice.recipient,
+            amount: remaining,
+            released_by: caller,
+            released_at: now,
+        }
+        .publish(&env);
+
+        Ok(true)
+    }
+
+    /// Freeze held funds until the arbiter resolves the dispute.
+    /// Only the payer may dispute, and only before the release timeout.
+    pub fn dispute(env: Env, invoice_id: u64, payer: Address) -> Result<bool, Error> {
+        payer.require_auth();
+
+        let (invoice, mut escrow) = Self::escrow_invoice(&env, invoice_id)?;
+        if invoice.payer.as_ref() != Some(&payer) {
+            return Err(Error::Unauthorized);
+        }
+
+        let now = env.ledger().timestamp();
+        let timed_out = escrow
+            .release_at
+            .is_some_and(|release_at| now >= release_at);
+        if escrow.status != EscrowStatus::Held || timed_out {
+            return Err(Error::InvalidEscrowState);
+        }
+
+        escrow.status = EscrowStatus::Disputed;
+        storage::save_escrow(&env, invoice_id, &escrow);
+
+        events::EscrowDisputed {
+            invoice_id,
+            payer,
+            disputed_at: now,
+        }
+        .publish(&env);
+
+        Ok(true)
+    }
+
+    /// Settle a disputed invoice: `refund_amount` goes back to the payer and the
+    /// rest of the held funds to the recipient, less the platform fee. Arbiter only.
+    pub fn resolve_dispute(
+        env: Env,
+        invoice_id: u64,
+        refund_amount: i128,
+        reason: String,
+    ) -> Result<bool, Error> {
+        let (mut invoice, mut escrow) = Self::escrow_invoice(&env, invoice_id)?;
+        escrow.arbiter.require_auth();
+
+        if escrow.status != EscrowStatus::Disputed {
+            return Err(Error::InvalidEscrowState);
+        }
+
+        let held = invoice.amount - invoice.refunded_total;
+        if refund_amount < 0 || refund_amount > held {
+            return Err(Error::InvalidAmount);
+        }
+
+        let payer = invoice.payer.clone().ok_or(Error::InvoiceNotFound)?;
+        if refund_amount > 0 {
+            let (shares_part, _) = invoice.split(refund_amount);
+            Self::refund_shares(&env, &invoice, shares_part);
+            token::Client::new(&env, &invoice.currency).transfer(
+                &env.current_contract_address(),
+                &payer,
+                &refund_amount,
+            );
+            Self::record_refund(
+                &env,
+                &mut invoice,
+                refund_amount,
+                reason,
+                escrow.arbiter.clone(),
+            );
+        }
+        let recipient_amount = Self::pay_out_held(&env, &invoice);
+
+        escrow.status = EscrowStatus::Resolved;
+        storage::save_invoice(&env, &invoice);
+        storage::save_escrow(&env, invoice_id, &escrow);
+
+        events::DisputeResolved {
+            invoice_id,
+            arbiter: escrow.arbiter,
+            refund_amount,
+            recipient_amount,
+            resolved_at: env.ledger().timestamp(),
+        }
+        .publish(&env);
+
+        Ok(true)
+    }
+
+    /// Get the escrow state of an escrow invoice
+    pub fn get_escrow(env: Env, invoice_id: u64) -> Result<Escrow, Error> {
+        let key = DataKey::Escrow(invoice_id);
+        let escrow = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .ok_or(Error::NotEscrowInvoice)?;
+        storage::bump(&env, &key);
+        Ok(escrow)
+    }
+}
+
+impl StellarAgentPayContract {
+    fn escrow_invoice(env: &Env, invoice_id: u64) -> Result<(Invoice, Escrow), Error> {
+        let invoice = Self::get_invoice(env.clone(), invoice_id)?;
+        let escrow = Self::get_escrow(env.clone(), invoice_id)?;
+        Ok((invoice, escrow))
+    }
+
+    /// Pay the funds still held for an invoice to its shares and the fee
+    /// recipient. Returns the amount paid to the shares.
+    fn pay_out_held(env: &Env, invoice: &Invoice) -> i128 {
+        let (shares_part, fee_part) = invoice.split(invoice.amount - invoice.refunded_total);
+        if let Some(fee_recipient) = invoice.fee_recipient.as_ref().filter(|_| fee_part > 0) {
+            token::Client::new(env, &invoice.currency).transfer(
+                &env.current_contract_address(),
+                fee_recipient,
+                &fee_part,
+            );
+        }
+        Self::release_shares(env, invoice);
+        shares_part
+    }
+}
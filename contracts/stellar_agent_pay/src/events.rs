@@ -0,0 +1,145 @@
+use soroban_sdk::{contractevent, Address, String};
+
+#[contractevent(topics = ["invoice_created"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvoiceCreated {
+    pub invoice_id: u64,
+    pub amount: i128,
+    pub currency: Address,
+    pub recipient: Address,
+    pub description: String,
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+    /// Set for escrow invoices
+    pub arbiter: Option<Address>,
+    pub release_timeout: Option<u64>,
+}
+
+#[contractevent(topics = ["invoice_paid"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvoicePaid {
+    pub invoice_id: u64,
+    pub payer: Address,
+    pub amount: i128,
+    pub currency: Address,
+    pub recipient: Address,
+    pub paid_at: u64,
+    /// Platform fee taken out of `amount`
+    pub fee_amount: i128,
+    /// Set when the payment is held in escrow until this timestamp
+    pub release_at: Option<u64>,
+}
+
+#[contractevent(topics = ["invoice_refunded"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvoiceRefunded {
+    pub invoice_id: u64,
+    pub payer: Address,
+    pub amount: i128,
+    pub refunded_total: i128,
+    pub reason: String,
+    pub initiator: Address,
+    pub refunded_at: u64,
+}
+
+#[contractevent(topics = ["invoice_expired"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvoiceExpired {
+    pub invoice_id: u64,
+    pub recipient: Address,
+    pub expired_at: u64,
+}
+
+#[contractevent(topics = ["escrow_released"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowReleased {
+    pub invoice_id: u64,
+    pub recipient: Address,
+    pub amount: i128,
+    pub released_by: Address,
+    pub released_at: u64,
+}
+
+#[contractevent(topics = ["escrow_disputed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscrowDisputed {
+    pub invoice_id: u64,
+    pub payer: Address,
+    pub disputed_at: u64,
+}
+
+#[contractevent(topics = ["dispute_resolved"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DisputeResolved {
+    pub invoice_id: u64,
+    pub arbiter: Address,
+    pub refund_amount: i128,
+    pub recipient_amount: i128,
+    pub resolved_at: u64,
+}
+
+#[contractevent(topics = ["recurring_scheduled"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecurringScheduled {
+    pub recurring_id: u64,
+    pub payer: Address,
+    pub amount: i128,
+    pub currency: Address,
+    pub recipient: Address,
+    pub interval_days: u32,
+    pub next_payment: u64,
+    pub max_payments: Option<u32>,
+    pub end_at: Option<u64>,
+}
+
+#[contractevent(topics = ["recurring_executed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecurringExecuted {
+    pub recurring_id: u64,
+    pub payer: Address,
+    pub amount: i128,
+    pub currency: Address,
+    pub recipient: Address,
+    pub executed_at: u64,
+    pub next_payment: u64,
+    pub payments_made: u32,
+}
+
+#[contractevent(topics = ["recurring_paused"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecurringPaused {
+    pub recurring_id: u64,
+    pub paused_at: u64,
+}
+
+#[contractevent(topics = ["recurring_resumed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecurringResumed {
+    pub recurring_id: u64,
+    pub resumed_at: u64,
+    pub next_payment: u64,
+}
+
+#[contractevent(topics = ["recurring_amount_updated"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecurringAmountUpdated {
+    pub recurring_id: u64,
+    pub old_amount: i128,
+    pub new_amount: i128,
+}
+
+#[contractevent(topics = ["recurring_completed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecurringCompleted {
+    pub recurring_id: u64,
+    pub payments_made: u32,
+    pub completed_at: u64,
+}
+
+#[contractevent(topics = ["recurring_cancelled"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecurringCancelled {
+    pub recurring_id: u64,
+    pub cancelled_by: Address,
+    pub cancelled_at: u64,
+}
@@ -0,0 +1,74 @@
+use admin_sep::AdministratableExtension;
+use soroban_sdk::{contractimpl, contracttype, Address, Env};
+
+use crate::{
+    DataKey, Error, Invoice, StellarAgentPayContract, StellarAgentPayContractArgs,
+    StellarAgentPayContractClient,
+};
+
+/// Basis-point denominator for `FeeConfig::fee_bps`
+pub const BPS_DENOMINATOR: u32 = 10_000;
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeConfig {
+    /// Share of each settled invoice taken as the platform fee
+    pub fee_bps: u32,
+    pub fee_recipient: Address,
+}
+
+impl FeeConfig {
+    /// Fee charged on `amount`, rounded down
+    pub fn fee_on(&self, amount: i128) -> i128 {
+        amount * i128::from(self.fee_bps) / i128::from(BPS_DENOMINATOR)
+    }
+}
+
+impl Invoice {
+    /// Part of the platform fee covered by the first `refunded` units refunded.
+    ///
+    /// Rounded down, so the fee share of each refund is the difference between the
+    /// running totals before and after it and a full refund returns the whole fee.
+    pub fn fee_refunded(&self, refunded: i128) -> i128 {
+        self.fee_amount * refunded / self.amount
+    }
+
+    /// Split the next `amount` of the invoice to be paid out or refunded into the
+    /// recipient's part and the platform fee's part
+    pub(crate) fn split(&self, amount: i128) -> (i128, i128) {
+        let fee = self.fee_refunded(self.refunded_total + amount)
+            - self.fee_refunded(self.refunded_total);
+        (amount - fee, fee)
+    }
+}
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// Set the platform fee taken from every invoice settled from now on (admin only).
+    /// `fee_bps` is out of 10 000; zero disables the fee.
+    ///
+    /// Refunds return the fee in proportion, pulled from `fee_recipient` through its
+    /// allowance to this contract, so it should keep one standing. Without it,
+    /// whoever initiates a refund covers the fee part themselves.
+    pub fn set_fee_config(env: Env, fee_bps: u32, fee_recipient: Address) -> Result<(), Error> {
+        Self::require_admin(&env);
+
+        if fee_bps > BPS_DENOMINATOR {
+            return Err(Error::InvalidFee);
+        }
+
+        env.storage().instance().set(
+            &DataKey::FeeConfig,
+            &FeeConfig {
+                fee_bps,
+                fee_recipient,
+            },
+        );
+        Ok(())
+    }
+
+    /// Get the platform fee configuration, if one has been set
+    pub fn get_fee_config(env: Env) -> Option<FeeConfig> {
+        env.storage().instance().get(&DataKey::FeeConfig)
+    }
+}
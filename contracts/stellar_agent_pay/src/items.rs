@@ -0,0 +1,113 @@
+use soroban_sdk::{contractimpl, contracttype, Address, Env, String, Vec};
+
+use crate::{
+    fees::BPS_DENOMINATOR, storage, DataKey, Error, InvoiceExpiry, StellarAgentPayContract,
+    StellarAgentPayContractArgs, StellarAgentPayContractClient,
+};
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineItem {
+    pub sku: String,
+    pub quantity: u32,
+    pub unit_price: i128,
+}
+
+/// Breakdown of an itemized invoice. The invoice amount is always
+/// `subtotal - discount + tax`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvoiceItems {
+    pub items: Vec<LineItem>,
+    /// Sum of `quantity * unit_price` over all items
+    pub subtotal: i128,
+    /// Flat amount taken off the subtotal before tax
+    pub discount: i128,
+    pub tax_bps: u32,
+    /// `tax_bps` of the discounted subtotal, rounded down
+    pub tax: i128,
+}
+
+impl InvoiceItems {
+    /// Price `items` and check that the resulting invoice total is positive
+    pub fn new(items: Vec<LineItem>, tax_bps: u32, discount: i128) -> Result<Self, Error> {
+        if items.is_empty() || tax_bps > BPS_DENOMINATOR {
+            return Err(Error::InvalidLineItems);
+        }
+
+        let mut subtotal: i128 = 0;
+        for item in items.iter() {
+            if item.quantity == 0 || item.unit_price < 0 {
+                return Err(Error::InvalidLineItems);
+            }
+            subtotal = item
+                .unit_price
+                .checked_mul(i128::from(item.quantity))
+                .and_then(|line_total| subtotal.checked_add(line_total))
+                .ok_or(Error::InvalidAmount)?;
+        }
+
+        if discount < 0 || discount >= subtotal {
+            return Err(Error::InvalidAmount);
+        }
+
+        let tax = (subtotal - discount)
+            .checked_mul(i128::from(tax_bps))
+            .ok_or(Error::InvalidAmount)?
+            / i128::from(BPS_DENOMINATOR);
+
+        Ok(InvoiceItems {
+            items,
+            subtotal,
+            discount,
+            tax_bps,
+            tax,
+        })
+    }
+
+    pub fn total(&self) -> i128 {
+        self.subtotal - self.discount + self.tax
+    }
+}
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// Create an invoice from line items. The amount is computed on-chain as the
+    /// item subtotal, less `discount`, plus `tax_bps` of the discounted subtotal.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_invoice_itemized(
+        env: Env,
+        currency: Address,
+        recipient: Address,
+        description: String,
+        items: Vec<LineItem>,
+        tax_bps: u32,
+        discount: i128,
+        expiry: Option<InvoiceExpiry>,
+    ) -> Result<u64, Error> {
+        let items = InvoiceItems::new(items, tax_bps, discount)?;
+        let invoice_id = Self::new_invoice(
+            &env,
+            items.total(),
+            currency,
+            recipient,
+            description,
+            expiry,
+            None,
+        )?;
+        storage::save(&env, &DataKey::InvoiceItems(invoice_id), &items);
+        Ok(invoice_id)
+    }
+
+    /// Get the line items of an itemized invoice
+    pub fn get_invoice_items(env: Env, invoice_id: u64) -> Result<InvoiceItems, Error> {
+        let key = DataKey::InvoiceItems(invoice_id);
+        let items = env
+            .storage()
+            .persistent()
+            .get(&key)
+            .ok_or(Error::NotItemizedInvoice)?;
+        storage::bump(&env, &key);
+        Ok(items)
+    }
+}
@@ -1,10 +1,27 @@
 #![no_std]
 use admin_sep::{Administratable, Upgradable};
 use soroban_sdk::{
-    contract, contractimpl, contracttype, contracterror, Address, Env, String,
+    contract, contractimpl, contracttype, contracterror, Address, Env, String, Vec,
     token
 };
 
+pub mod escrow;
+pub mod events;
+pub mod fees;
+pub mod items;
+pub mod listing;
+pub mod recurring;
+pub mod router;
+pub mod signed;
+pub mod split;
+pub mod storage;
+
+pub use escrow::{Escrow, EscrowStatus};
+pub use fees::FeeConfig;
+pub use items::{InvoiceItems, LineItem};
+pub use signed::InvoiceTerms;
+pub use split::Share;
+
 #[contract]
 pub struct StellarAgentPayContract;
 
@@ -15,6 +32,7 @@ pub enum InvoiceStatus {
     Paid,
     Refunded,
     Expired,
+    PartiallyRefunded,
 }
 
 #[contracttype]
@@ -29,6 +47,36 @@ pub struct Invoice {
     pub description: String,
     pub created_at: u64,
     pub paid_at: Option<u64>,
+    pub expires_at: Option<u64>,
+    pub refunded_total: i128,
+    /// Platform fee taken out of `amount` when the invoice was settled
+    pub fee_amount: i128,
+    pub fee_recipient: Option<Address>,
+}
+
+impl Invoice {
+    /// Whether a pending invoice is past its expiry at `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InvoiceExpiry {
+    /// Expires at an absolute ledger timestamp
+    At(u64),
+    /// Expires this many seconds after creation
+    After(u64),
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RefundRecord {
+    pub amount: i128,
+    pub reason: String,
+    pub timestamp: u64,
+    pub initiator: Address,
 }
 
 #[contracttype]
@@ -40,18 +88,48 @@ pub struct PaymentConfig {
     pub refund_window_hours: u32,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RecurringStatus {
+    Active,
+    Paused,
+    Cancelled,
+    /// Reached `max_payments` or `end_at`
+    Completed,
+}
+
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RecurringPayment {
     pub id: u64,
+    pub payer: Address,
     pub amount: i128,
     pub currency: Address,
     pub recipient: Address,
     pub interval_days: u32,
     pub next_payment: u64,
-    pub active: bool,
+    pub status: RecurringStatus,
+    pub payments_made: u32,
+    pub max_payments: Option<u32>,
+    /// No payments are collected for periods scheduled after this timestamp
+    pub end_at: Option<u64>,
 }
 
+impl RecurringPayment {
+    pub fn interval_seconds(&self) -> u64 {
+        u64::from(self.interval_days) * 24 * 60 * 60
+    }
+
+    /// Whether the subscription has collected its last payment
+    pub fn is_finished(&self) -> bool {
+        self.max_payments.is_some_and(|max| self.payments_made >= max)
+            || self.end_at.is_some_and(|end_at| self.next_payment > end_at)
+    }
+}
+
+/// Missed recurring periods older than this are skipped rather than collected
+pub const MAX_CATCH_UP_PERIODS: u64 = 3;
+
 #[contracttype]
 pub enum DataKey {
     InvoiceCounter,
@@ -59,6 +137,19 @@ pub enum DataKey {
     PaymentConfig(Address),
     RecurringPayment(u64),
     RecurringCounter,
+    Refunds(u64),
+    Escrow(u64),
+    Router,
+    RecipientInvoiceCount(Address),
+    RecipientInvoice(Address, u32),
+    PayerInvoiceCount(Address),
+    PayerInvoice(Address, u32),
+    FeeConfig,
+    InvoiceItems(u64),
+    SigningKey(Address),
+    InvoiceNonce(Address, u64),
+    Shares(u64),
+    ShareBalances(u64),
 }
 
 #[contracterror]
@@ -71,6 +162,20 @@ pub enum Error {
     Unauthorized = 4,
     InvalidAmount = 5,
     InvoiceExpired = 6,
+    InvalidExpiry = 7,
+    RefundWindowClosed = 8,
+    NotEscrowInvoice = 9,
+    InvalidEscrowState = 10,
+    InvalidInterval = 11,
+    InvalidRecurringState = 12,
+    RouterNotSet = 13,
+    MaxAmountInExceeded = 14,
+    InvalidFee = 15,
+    InvalidLineItems = 16,
+    NotItemizedInvoice = 17,
+    SigningKeyNotSet = 18,
+    NonceAlreadyUsed = 19,
+    InvalidShares = 20,
 }
 
 #[contractimpl]
@@ -85,100 +190,170 @@ impl StellarAgentPayContract {
         Self::set_admin(env, admin);
         env.storage().instance().set(&DataKey::InvoiceCounter, &0u64);
         env.storage().instance().set(&DataKey::RecurringCounter, &0u64);
+        storage::bump_instance(env);
     }
 
-    /// Create a new invoice for payment
+    /// Create a new invoice for payment, optionally only payable until an expiry
     pub fn create_invoice(
         env: Env,
         amount: i128,
         currency: Address,
         recipient: Address,
         description: String,
+        expiry: Option<InvoiceExpiry>,
     ) -> Result<u64, Error> {
-        if amount <= 0 {
-            return Err(Error::InvalidAmount);
-        }
-
-        let counter: u64 = env.storage().instance()
-            .get(&DataKey::InvoiceCounter)
-            .unwrap_or(0);
-        
-        let invoice_id = counter + 1;
-        
-        let invoice = Invoice {
-            id: invoice_id,
-            amount,
-            currency,
-            recipient,
-            payer: None,
-            status: InvoiceStatus::Pending,
-            description,
-            created_at: env.ledger().timestamp(),
-            paid_at: None,
-        };
-
-        env.storage().persistent().set(&DataKey::Invoice(invoice_id), &invoice);
-        env.storage().instance().set(&DataKey::InvoiceCounter, &invoice_id);
-
-        Ok(invoice_id)
+        Self::new_invoice(&env, amount, currency, recipient, description, expiry, None)
     }
 
-    /// Process payment for an invoice
+    /// Process payment for an invoice. Escrow invoices are paid into the contract.
     pub fn process_payment(env: Env, invoice_id: u64, payer: Address) -> Result<bool, Error> {
         payer.require_auth();
 
-        let mut invoice: Invoice = env.storage().persistent()
-            .get(&DataKey::Invoice(invoice_id))
-            .ok_or(Error::InvoiceNotFound)?;
+        let invoice = Self::payable_invoice(&env, invoice_id)?;
+        Self::settle(&env, invoice, payer.clone(), &payer);
 
-        if invoice.status != InvoiceStatus::Pending {
-            return Err(Error::InvoiceAlreadyPaid);
-        }
+        Ok(true)
+    }
 
-        // Transfer tokens from payer to recipient
-        let token_client = token::Client::new(&env, &invoice.currency);
-        token_client.transfer(&payer, &invoice.recipient, &invoice.amount);
+    /// Get invoice details
+    pub fn get_invoice(env: Env, invoice_id: u64) -> Result<Invoice, Error> {
+        storage::load_invoice(&env, invoice_id)
+    }
 
-        // Update invoice status
-        invoice.status = InvoiceStatus::Paid;
-        invoice.payer = Some(payer);
-        invoice.paid_at = Some(env.ledger().timestamp());
+    /// Extend the TTL of an invoice and its escrow, line item, split and refund
+    /// records, and of the contract instance, to the maximum. Anyone may call this
+    /// to keep an idle invoice from being archived.
+    pub fn extend_invoice_ttl(env: Env, invoice_id: u64) -> Result<(), Error> {
+        storage::load_invoice(&env, invoice_id)?;
+        storage::bump_instance(&env);
+        storage::bump(&env, &DataKey::Escrow(invoice_id));
+        storage::bump(&env, &DataKey::InvoiceItems(invoice_id));
+        storage::bump(&env, &DataKey::Shares(invoice_id));
+        storage::bump(&env, &DataKey::ShareBalances(invoice_id));
+        storage::bump(&env, &DataKey::Refunds(invoice_id));
+        Ok(())
+    }
 
-        env.storage().persistent().set(&DataKey::Invoice(invoice_id), &invoice);
+    /// Mark a pending invoice as expired once its expiry has passed.
+    /// Anyone may call this; returns `false` if the invoice is not yet expired.
+    pub fn expire_invoice(env: Env, invoice_id: u64) -> Result<bool, Error> {
+        let invoice = storage::load_invoice(&env, invoice_id)?;
 
-        Ok(true)
+        Ok(Self::try_expire(&env, invoice))
     }
 
-    /// Get invoice details
-    pub fn get_invoice(env: Env, invoice_id: u64) -> Result<Invoice, Error> {
-        env.storage().persistent()
-            .get(&DataKey::Invoice(invoice_id))
-            .ok_or(Error::InvoiceNotFound)
+    /// Expire every stale pending invoice in `invoice_ids`, skipping unknown IDs.
+    /// Returns the number of invoices that were expired.
+    pub fn expire_invoices(env: Env, invoice_ids: Vec<u64>) -> u32 {
+        let mut expired = 0;
+        for invoice_id in invoice_ids.iter() {
+            if let Ok(invoice) = storage::load_invoice(&env, invoice_id) {
+                if Self::try_expire(&env, invoice) {
+                    expired += 1;
+                }
+            }
+        }
+        expired
     }
 
-    /// Execute refund for a paid invoice
-    pub fn execute_refund(env: Env, invoice_id: u64, _reason: String) -> Result<bool, Error> {
-        let mut invoice: Invoice = env.storage().persistent()
-            .get(&DataKey::Invoice(invoice_id))
-            .ok_or(Error::InvoiceNotFound)?;
+    /// Refund all or part of a paid invoice.
+    ///
+    /// The recipient or the admin may refund while the merchant's refund window is
+    /// open. If the merchant enabled auto refunds, the payer may claim it alone.
+    /// Refunds not initiated by the recipient are pulled through the allowance the
+    /// merchant granted this contract, unless the funds are still held in escrow.
+    /// The platform fee and the shares of a split invoice are refunded in
+    /// proportion, each pulled through its recipient's allowance to this contract.
+    /// If the fee recipient hasn't allowed enough, the initiator covers the fee part.
+    /// Disputed escrow invoices can only be settled through `resolve_dispute`.
+    pub fn execute_refund(
+        env: Env,
+        invoice_id: u64,
+        refund_amount: i128,
+        reason: String,
+        initiator: Address,
+    ) -> Result<bool, Error> {
+        initiator.require_auth();
+        storage::bump_instance(&env);
+
+        let mut invoice = storage::load_invoice(&env, invoice_id)?;
 
-        if invoice.status != InvoiceStatus::Paid {
+        if !matches!(invoice.status, InvoiceStatus::Paid | InvoiceStatus::PartiallyRefunded) {
             return Err(Error::InvoiceNotFound);
         }
 
-        let payer = invoice.payer.as_ref().ok_or(Error::InvoiceNotFound)?;
-        
-        // Transfer tokens back from recipient to payer
+        if refund_amount <= 0 || refund_amount > invoice.amount - invoice.refunded_total {
+            return Err(Error::InvalidAmount);
+        }
+
+        let escrow_status = Self::get_escrow(env.clone(), invoice_id)
+            .ok()
+            .map(|escrow| escrow.status);
+        if escrow_status == Some(EscrowStatus::Disputed) {
+            return Err(Error::InvalidEscrowState);
+        }
+
+        let payer = invoice.payer.clone().ok_or(Error::InvoiceNotFound)?;
+        let paid_at = invoice.paid_at.ok_or(Error::InvoiceNotFound)?;
+        let config: Option<PaymentConfig> = env.storage().persistent()
+            .get(&DataKey::PaymentConfig(invoice.recipient.clone()));
+
+        if let Some(config) = &config {
+            let window = u64::from(config.refund_window_hours) * 60 * 60;
+            if env.ledger().timestamp() > paid_at.saturating_add(window) {
+                return Err(Error::RefundWindowClosed);
+            }
+        }
+
+        let auto_refund = config.is_some_and(|config| config.auto_refund_enabled);
+        if initiator != invoice.recipient
+            && initiator != Self::admin(&env)
+            && !(initiator == payer && auto_refund)
+        {
+            return Err(Error::Unauthorized);
+        }
+
         let token_client = token::Client::new(&env, &invoice.currency);
-        token_client.transfer(&invoice.recipient, payer, &invoice.amount);
+        let contract_address = env.current_contract_address();
+        let (merchant_part, fee_part) = invoice.split(refund_amount);
+        let share_parts = Self::refund_shares(&env, &invoice, merchant_part);
+        if escrow_status == Some(EscrowStatus::Held) {
+            token_client.transfer(&contract_address, &payer, &refund_amount);
+        } else {
+            for (recipient, part) in share_parts.iter() {
+                if part == 0 {
+                    continue;
+                }
+                if initiator == recipient {
+                    token_client.transfer(&recipient, &payer, &part);
+                } else {
+                    token_client.transfer_from(&contract_address, &recipient, &payer, &part);
+                }
+            }
+            if let Some(fee_recipient) = invoice.fee_recipient.as_ref().filter(|_| fee_part > 0) {
+                if initiator != *fee_recipient
+                    && token_client.allowance(fee_recipient, &contract_address) >= fee_part
+                {
+                    token_client.transfer_from(&contract_address, fee_recipient, &payer, &fee_part);
+                } else if initiator != payer {
+                    token_client.transfer(&initiator, &payer, &fee_part);
+                }
+            }
+        }
 
-        // Update invoice status
-        invoice.status = InvoiceStatus::Refunded;
-        env.storage().persistent().set(&DataKey::Invoice(invoice_id), &invoice);
+        Self::record_refund(&env, &mut invoice, refund_amount, reason, initiator);
+        storage::save_invoice(&env, &invoice);
 
         Ok(true)
     }
 
+    /// Get the refunds issued against an invoice, oldest first
+    pub fn get_refunds(env: Env, invoice_id: u64) -> Vec<RefundRecord> {
+        env.storage().persistent()
+            .get(&DataKey::Refunds(invoice_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
     /// Set merchant payment preferences
     pub fn update_merchant_preferences(
         env: Env,
@@ -203,76 +378,237 @@ impl StellarAgentPayContract {
     pub fn get_merchant_config(env: Env, merchant: Address) -> Option<PaymentConfig> {
         env.storage().persistent().get(&DataKey::PaymentConfig(merchant))
     }
+}
 
-    /// Schedule recurring payment
-    pub fn schedule_recurring(
-        env: Env,
+impl StellarAgentPayContract {
+    /// Load an invoice that can still be paid
+    fn payable_invoice(env: &Env, invoice_id: u64) -> Result<Invoice, Error> {
+        let invoice = storage::load_invoice(env, invoice_id)?;
+
+        match invoice.status {
+            InvoiceStatus::Pending => {}
+            InvoiceStatus::Expired => return Err(Error::InvoiceExpired),
+            _ => return Err(Error::InvoiceAlreadyPaid),
+        }
+
+        if invoice.is_expired(env.ledger().timestamp()) {
+            return Err(Error::InvoiceExpired);
+        }
+
+        Ok(invoice)
+    }
+
+    /// Move `invoice.amount` from `source` to the recipient or its shares (or escrow),
+    /// less the platform fee, and mark the invoice paid by `payer`
+    fn settle(env: &Env, mut invoice: Invoice, payer: Address, source: &Address) {
+        storage::bump_instance(env);
+        let invoice_id = invoice.id;
+        let now = env.ledger().timestamp();
+        let mut release_at = None;
+        let held = match Self::get_escrow(env.clone(), invoice_id) {
+            Ok(mut escrow) => {
+                escrow.status = EscrowStatus::Held;
+                escrow.release_at = Some(now.saturating_add(escrow.release_timeout));
+                release_at = escrow.release_at;
+                storage::save_escrow(env, invoice_id, &escrow);
+                true
+            }
+            Err(_) => false,
+        };
+
+        if let Some(fee_config) = Self::get_fee_config(env.clone()) {
+            invoice.fee_amount = fee_config.fee_on(invoice.amount);
+            invoice.fee_recipient = Some(fee_config.fee_recipient);
+        }
+
+        // Escrowed funds stay whole in the contract until released
+        let token_client = token::Client::new(env, &invoice.currency);
+        if held {
+            let contract_address = env.current_contract_address();
+            token_client.transfer(source, &contract_address, &invoice.amount);
+        } else if let Some(fee_recipient) = &invoice.fee_recipient {
+            if invoice.fee_amount > 0 {
+                token_client.transfer(source, fee_recipient, &invoice.fee_amount);
+            }
+        }
+        Self::pay_shares(env, &invoice, source, held);
+
+        // Update invoice status
+        invoice.status = InvoiceStatus::Paid;
+        invoice.payer = Some(payer.clone());
+        invoice.paid_at = Some(now);
+
+        storage::save_invoice(env, &invoice);
+        listing::InvoiceIndex::Payer(payer.clone()).push(env, invoice_id);
+
+        events::InvoicePaid {
+            invoice_id,
+            payer,
+            amount: invoice.amount,
+            currency: invoice.currency,
+            recipient: invoice.recipient,
+            paid_at: now,
+            fee_amount: invoice.fee_amount,
+            release_at,
+        }
+        .publish(env);
+    }
+
+    fn new_invoice(
+        env: &Env,
         amount: i128,
         currency: Address,
         recipient: Address,
-        interval_days: u32,
-        payer: Address,
+        description: String,
+        expiry: Option<InvoiceExpiry>,
+        escrow: Option<Escrow>,
     ) -> Result<u64, Error> {
-        payer.require_auth();
-
         if amount <= 0 {
             return Err(Error::InvalidAmount);
         }
 
+        let now = env.ledger().timestamp();
+        let expires_at = match expiry {
+            None => None,
+            Some(InvoiceExpiry::At(timestamp)) if timestamp > now => Some(timestamp),
+            Some(InvoiceExpiry::After(seconds)) if seconds > 0 => {
+                Some(now.checked_add(seconds).ok_or(Error::InvalidExpiry)?)
+            }
+            Some(_) => return Err(Error::InvalidExpiry),
+        };
+
         let counter: u64 = env.storage().instance()
-            .get(&DataKey::RecurringCounter)
+            .get(&DataKey::InvoiceCounter)
             .unwrap_or(0);
         
-        let recurring_id = counter + 1;
+        let invoice_id = counter + 1;
         
-        let recurring_payment = RecurringPayment {
-            id: recurring_id,
+        let invoice = Invoice {
+            id: invoice_id,
             amount,
             currency,
             recipient,
-            interval_days,
-            next_payment: env.ledger().timestamp() + (interval_days as u64 * 24 * 60 * 60),
-            active: true,
+            payer: None,
+            status: InvoiceStatus::Pending,
+            description,
+            created_at: now,
+            paid_at: None,
+            expires_at,
+            refunded_total: 0,
+            fee_amount: 0,
+            fee_recipient: None,
         };
 
-        env.storage().persistent().set(&DataKey::RecurringPayment(recurring_id), &recurring_payment);
-        env.storage().instance().set(&DataKey::RecurringCounter, &recurring_id);
+        storage::save_invoice(env, &invoice);
+        if let Some(escrow) = &escrow {
+            storage::save_escrow(env, invoice_id, escrow);
+        }
+        env.storage().instance().set(&DataKey::InvoiceCounter, &invoice_id);
+        storage::bump_instance(env);
+        listing::InvoiceIndex::Recipient(invoice.recipient.clone()).push(env, invoice_id);
+
+        events::InvoiceCreated {
+            invoice_id,
+            amount,
+            currency: invoice.currency,
+            recipient: invoice.recipient,
+            description: invoice.description,
+            created_at: now,
+            expires_at,
+            arbiter: escrow.as_ref().map(|escrow| escrow.arbiter.clone()),
+            release_timeout: escrow.map(|escrow| escrow.release_timeout),
+        }
+        .publish(env);
 
-        Ok(recurring_id)
+        Ok(invoice_id)
     }
 
-    /// Execute recurring payment (called by automation)
-    pub fn execute_recurring_payment(env: Env, recurring_id: u64, payer: Address) -> Result<bool, Error> {
-        let mut recurring: RecurringPayment = env.storage().persistent()
-            .get(&DataKey::RecurringPayment(recurring_id))
-            .ok_or(Error::InvoiceNotFound)?;
+    /// Add `amount` to the invoice's refunded total and refund history.
+    /// The caller is responsible for moving the funds and saving the invoice.
+    fn record_refund(
+        env: &Env,
+        invoice: &mut Invoice,
+        amount: i128,
+        reason: String,
+        initiator: Address,
+    ) {
+        invoice.refunded_total += amount;
+        invoice.status = if invoice.refunded_total == invoice.amount {
+            InvoiceStatus::Refunded
+        } else {
+            InvoiceStatus::PartiallyRefunded
+        };
 
-        if !recurring.active || env.ledger().timestamp() < recurring.next_payment {
-            return Ok(false);
+        let now = env.ledger().timestamp();
+        let mut refunds = Self::get_refunds(env.clone(), invoice.id);
+        refunds.push_back(RefundRecord {
+            amount,
+            reason: reason.clone(),
+            timestamp: now,
+            initiator: initiator.clone(),
+        });
+        storage::save_refunds(env, invoice.id, &refunds);
+
+        if let Some(payer) = invoice.payer.clone() {
+            events::InvoiceRefunded {
+                invoice_id: invoice.id,
+                payer,
+                amount,
+                refunded_total: invoice.refunded_total,
+                reason,
+                initiator,
+                refunded_at: now,
+            }
+            .publish(env);
         }
+    }
 
-        // Transfer tokens
-        let token_client = token::Client::new(&env, &recurring.currency);
-        token_client.transfer(&payer, &recurring.recipient, &recurring.amount);
+    /// Load a recurring payment after checking `payer` owns it and authorized the call
+    fn owned_recurring(
+        env: &Env,
+        recurring_id: u64,
+        payer: &Address,
+    ) -> Result<RecurringPayment, Error> {
+        payer.require_auth();
 
-        // Update next payment time
-        recurring.next_payment = env.ledger().timestamp() + (recurring.interval_days as u64 * 24 * 60 * 60);
-        env.storage().persistent().set(&DataKey::RecurringPayment(recurring_id), &recurring);
+        let recurring = Self::get_recurring_payment(env.clone(), recurring_id)?;
+        if recurring.payer != *payer {
+            return Err(Error::Unauthorized);
+        }
+        Ok(recurring)
+    }
 
-        Ok(true)
+    fn complete_recurring(env: &Env, mut recurring: RecurringPayment) {
+        recurring.status = RecurringStatus::Completed;
+        storage::save_recurring(env, &recurring);
+
+        events::RecurringCompleted {
+            recurring_id: recurring.id,
+            payments_made: recurring.payments_made,
+            completed_at: env.ledger().timestamp(),
+        }
+        .publish(env);
     }
 
-    /// Cancel recurring payment
-    pub fn cancel_recurring(env: Env, recurring_id: u64, payer: Address) -> Result<bool, Error> {
-        payer.require_auth();
+    fn try_expire(env: &Env, mut invoice: Invoice) -> bool {
+        let now = env.ledger().timestamp();
+        if invoice.status != InvoiceStatus::Pending || !invoice.is_expired(now) {
+            return false;
+        }
 
-        let mut recurring: RecurringPayment = env.storage().persistent()
-            .get(&DataKey::RecurringPayment(recurring_id))
-            .ok_or(Error::InvoiceNotFound)?;
+        invoice.status = InvoiceStatus::Expired;
+        storage::save_invoice(env, &invoice);
 
-        recurring.active = false;
-        env.storage().persistent().set(&DataKey::RecurringPayment(recurring_id), &recurring);
+        events::InvoiceExpired {
+            invoice_id: invoice.id,
+            recipient: invoice.recipient,
+            expired_at: now,
+        }
+        .publish(env);
 
-        Ok(true)
+        true
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test;
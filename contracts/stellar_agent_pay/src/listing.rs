@@ -0,0 +1,111 @@
+use soroban_sdk::{contractimpl, Address, Env, Vec};
+
+use crate::{
+    storage, DataKey, Invoice, InvoiceStatus, StellarAgentPayContract, StellarAgentPayContractArgs,
+    StellarAgentPayContractClient,
+};
+
+/// Maximum number of index positions read by a single listing call. Each
+/// position costs two ledger reads, keeping a page within the footprint limit.
+pub const MAX_PAGE_SIZE: u32 = 40;
+
+/// Append-only list of invoice IDs, stored one entry per position
+pub(crate) enum InvoiceIndex {
+    Recipient(Address),
+    Payer(Address),
+}
+
+impl InvoiceIndex {
+    fn count_key(&self) -> DataKey {
+        match self {
+            Self::Recipient(recipient) => DataKey::RecipientInvoiceCount(recipient.clone()),
+            Self::Payer(payer) => DataKey::PayerInvoiceCount(payer.clone()),
+        }
+    }
+
+    fn entry_key(&self, position: u32) -> DataKey {
+        match self {
+            Self::Recipient(recipient) => DataKey::RecipientInvoice(recipient.clone(), position),
+            Self::Payer(payer) => DataKey::PayerInvoice(payer.clone(), position),
+        }
+    }
+
+    pub(crate) fn count(&self, env: &Env) -> u32 {
+        env.storage()
+            .persistent()
+            .get(&self.count_key())
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn push(&self, env: &Env, invoice_id: u64) {
+        let count = self.count(env);
+        storage::save(env, &self.entry_key(count), &invoice_id);
+        storage::save(env, &self.count_key(), &(count + 1));
+    }
+
+    /// Invoices at positions `start..start + limit` that match `status`, if given
+    fn page(
+        &self,
+        env: &Env,
+        start: u32,
+        limit: u32,
+        status: Option<InvoiceStatus>,
+    ) -> Vec<Invoice> {
+        let end = start
+            .saturating_add(limit.min(MAX_PAGE_SIZE))
+            .min(self.count(env));
+        let mut invoices = Vec::new(env);
+        for position in start..end {
+            let entry_key = self.entry_key(position);
+            let invoice_id: u64 = env.storage().persistent().get(&entry_key).unwrap();
+            storage::bump(env, &entry_key);
+            let invoice = storage::load_invoice(env, invoice_id).unwrap();
+            if status
+                .as_ref()
+                .is_none_or(|status| *status == invoice.status)
+            {
+                invoices.push_back(invoice);
+            }
+        }
+        invoices
+    }
+}
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// List the invoices issued to `recipient`, oldest first.
+    ///
+    /// Reads index positions `start..start + limit`, with `limit` capped at
+    /// `MAX_PAGE_SIZE`, and returns the invoices matching `status` if one is given.
+    pub fn list_invoices_by_recipient(
+        env: Env,
+        recipient: Address,
+        start: u32,
+        limit: u32,
+        status: Option<InvoiceStatus>,
+    ) -> Vec<Invoice> {
+        InvoiceIndex::Recipient(recipient).page(&env, start, limit, status)
+    }
+
+    /// Number of invoices issued to `recipient`
+    pub fn count_invoices_by_recipient(env: Env, recipient: Address) -> u32 {
+        InvoiceIndex::Recipient(recipient).count(&env)
+    }
+
+    /// List the invoices paid by `payer`, in payment order. Paging works as in
+    /// `list_invoices_by_recipient`.
+    pub fn list_invoices_by_payer(
+        env: Env,
+        payer: Address,
+        start: u32,
+        limit: u32,
+        status: Option<InvoiceStatus>,
+    ) -> Vec<Invoice> {
+        InvoiceIndex::Payer(payer).page(&env, start, limit, status)
+    }
+
+    /// Number of invoices paid by `payer`
+    pub fn count_invoices_by_payer(env: Env, payer: Address) -> u32 {
+        InvoiceIndex::Payer(payer).count(&env)
+    }
+}
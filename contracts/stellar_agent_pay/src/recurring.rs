@@ -0,0 +1,271 @@
+// `schedule_recurring` takes too many arguments for clippy, and so does the
+// argument helper `contractargs` generates for it, which a `#[allow]` on the
+// function itself doesn't reach
+#![allow(clippy::too_many_arguments)]
+
+use soroban_sdk::{contractimpl, token, Address, Env};
+
+use crate::{
+    events, storage, DataKey, Error, RecurringPayment, RecurringStatus, StellarAgentPayContract,
+    StellarAgentPayContractArgs, StellarAgentPayContractClient, MAX_CATCH_UP_PERIODS,
+};
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// Schedule recurring payment.
+    ///
+    /// The payer approves this contract to pull `allowance_periods` payments, on top
+    /// of any allowance it already has, valid for as long as the network allows.
+    /// The payer can top the allowance up later by approving the contract directly.
+    /// The subscription ends after `max_payments` payments or at `end_at`, if set.
+    pub fn schedule_recurring(
+        env: Env,
+        amount: i128,
+        currency: Address,
+        recipient: Address,
+        interval_days: u32,
+        payer: Address,
+        allowance_periods: u32,
+        max_payments: Option<u32>,
+        end_at: Option<u64>,
+    ) -> Result<u64, Error> {
+        payer.require_auth();
+
+        if amount <= 0 || allowance_periods == 0 || max_payments == Some(0) {
+            return Err(Error::InvalidAmount);
+        }
+
+        if interval_days == 0 {
+            return Err(Error::InvalidInterval);
+        }
+
+        if end_at.is_some_and(|end_at| end_at <= env.ledger().timestamp()) {
+            return Err(Error::InvalidExpiry);
+        }
+
+        let contract_address = env.current_contract_address();
+        let token_client = token::Client::new(&env, &currency);
+        let allowance = token_client.allowance(&payer, &contract_address)
+            + amount * i128::from(allowance_periods);
+        token_client.approve(
+            &payer,
+            &contract_address,
+            &allowance,
+            &env.ledger().max_live_until_ledger(),
+        );
+
+        let counter: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::RecurringCounter)
+            .unwrap_or(0);
+
+        let recurring_id = counter + 1;
+
+        let mut recurring_payment = RecurringPayment {
+            id: recurring_id,
+            payer: payer.clone(),
+            amount,
+            currency,
+            recipient,
+            interval_days,
+            next_payment: env.ledger().timestamp(),
+            status: RecurringStatus::Active,
+            payments_made: 0,
+            max_payments,
+            end_at,
+        };
+        recurring_payment.next_payment += recurring_payment.interval_seconds();
+
+        storage::save_recurring(&env, &recurring_payment);
+        env.storage()
+            .instance()
+            .set(&DataKey::RecurringCounter, &recurring_id);
+        storage::bump_instance(&env);
+
+        events::RecurringScheduled {
+            recurring_id,
+            payer,
+            amount: recurring_payment.amount,
+            currency: recurring_payment.currency,
+            recipient: recurring_payment.recipient,
+            interval_days: recurring_payment.interval_days,
+            next_payment: recurring_payment.next_payment,
+            max_payments,
+            end_at,
+        }
+        .publish(&env);
+
+        Ok(recurring_id)
+    }
+
+    /// Collect one due period of a recurring payment from the payer's allowance.
+    ///
+    /// Anyone may call this (typically a keeper bot). Periods stay anchored to the
+    /// original schedule, so a keeper that fell behind catches up by calling again
+    /// while payments are due; at most `MAX_CATCH_UP_PERIODS` missed periods are
+    /// collected and older ones are skipped. Returns `false` if nothing is due.
+    pub fn execute_recurring_payment(env: Env, recurring_id: u64) -> Result<bool, Error> {
+        storage::bump_instance(&env);
+        let mut recurring = Self::get_recurring_payment(env.clone(), recurring_id)?;
+
+        let now = env.ledger().timestamp();
+        if recurring.status != RecurringStatus::Active || now < recurring.next_payment {
+            return Ok(false);
+        }
+
+        let interval = recurring.interval_seconds();
+        let periods_due = (now - recurring.next_payment) / interval + 1;
+        if periods_due > MAX_CATCH_UP_PERIODS {
+            recurring.next_payment += (periods_due - MAX_CATCH_UP_PERIODS) * interval;
+        }
+
+        if recurring.is_finished() {
+            Self::complete_recurring(&env, recurring);
+            return Ok(false);
+        }
+
+        // Pull exactly one period's amount through the payer's allowance
+        let token_client = token::Client::new(&env, &recurring.currency);
+        token_client.transfer_from(
+            &env.current_contract_address(),
+            &recurring.payer,
+            &recurring.recipient,
+            &recurring.amount,
+        );
+
+        // Update next payment time
+        recurring.next_payment += interval;
+        recurring.payments_made += 1;
+        storage::save_recurring(&env, &recurring);
+
+        events::RecurringExecuted {
+            recurring_id,
+            payer: recurring.payer.clone(),
+            amount: recurring.amount,
+            currency: recurring.currency.clone(),
+            recipient: recurring.recipient.clone(),
+            executed_at: now,
+            next_payment: recurring.next_payment,
+            payments_made: recurring.payments_made,
+        }
+        .publish(&env);
+
+        if recurring.is_finished() {
+            Self::complete_recurring(&env, recurring);
+        }
+
+        Ok(true)
+    }
+
+    /// Get recurring payment details
+    pub fn get_recurring_payment(env: Env, recurring_id: u64) -> Result<RecurringPayment, Error> {
+        storage::load_recurring(&env, recurring_id)
+    }
+
+    /// Pause an active recurring payment. Periods that fall due while paused are skipped.
+    pub fn pause_recurring(env: Env, recurring_id: u64, payer: Address) -> Result<bool, Error> {
+        let mut recurring = Self::owned_recurring(&env, recurring_id, &payer)?;
+        if recurring.status != RecurringStatus::Active {
+            return Err(Error::InvalidRecurringState);
+        }
+
+        recurring.status = RecurringStatus::Paused;
+        storage::save_recurring(&env, &recurring);
+
+        events::RecurringPaused {
+            recurring_id,
+            paused_at: env.ledger().timestamp(),
+        }
+        .publish(&env);
+
+        Ok(true)
+    }
+
+    /// Resume a paused recurring payment on its original schedule
+    pub fn resume_recurring(env: Env, recurring_id: u64, payer: Address) -> Result<bool, Error> {
+        let mut recurring = Self::owned_recurring(&env, recurring_id, &payer)?;
+        if recurring.status != RecurringStatus::Paused {
+            return Err(Error::InvalidRecurringState);
+        }
+
+        // Skip the periods that fell due while paused
+        let now = env.ledger().timestamp();
+        if recurring.next_payment < now {
+            let interval = recurring.interval_seconds();
+            let skipped = (now - recurring.next_payment).div_ceil(interval);
+            recurring.next_payment += skipped * interval;
+        }
+
+        recurring.status = RecurringStatus::Active;
+        storage::save_recurring(&env, &recurring);
+
+        events::RecurringResumed {
+            recurring_id,
+            resumed_at: now,
+            next_payment: recurring.next_payment,
+        }
+        .publish(&env);
+
+        Ok(true)
+    }
+
+    /// Change the amount of each future payment. Requires both the payer and the
+    /// recipient to authorize the change.
+    pub fn update_recurring_amount(
+        env: Env,
+        recurring_id: u64,
+        payer: Address,
+        new_amount: i128,
+    ) -> Result<bool, Error> {
+        let mut recurring = Self::owned_recurring(&env, recurring_id, &payer)?;
+        recurring.recipient.require_auth();
+
+        if new_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        if matches!(
+            recurring.status,
+            RecurringStatus::Cancelled | RecurringStatus::Completed
+        ) {
+            return Err(Error::InvalidRecurringState);
+        }
+
+        let old_amount = recurring.amount;
+        recurring.amount = new_amount;
+        storage::save_recurring(&env, &recurring);
+
+        events::RecurringAmountUpdated {
+            recurring_id,
+            old_amount,
+            new_amount,
+        }
+        .publish(&env);
+
+        Ok(true)
+    }
+
+    /// Cancel recurring payment
+    pub fn cancel_recurring(env: Env, recurring_id: u64, payer: Address) -> Result<bool, Error> {
+        let mut recurring = Self::owned_recurring(&env, recurring_id, &payer)?;
+        if matches!(
+            recurring.status,
+            RecurringStatus::Cancelled | RecurringStatus::Completed
+        ) {
+            return Err(Error::InvalidRecurringState);
+        }
+
+        recurring.status = RecurringStatus::Cancelled;
+        storage::save_recurring(&env, &recurring);
+
+        events::RecurringCancelled {
+            recurring_id,
+            cancelled_by: payer,
+            cancelled_at: env.ledger().timestamp(),
+        }
+        .publish(&env);
+
+        Ok(true)
+    }
+}
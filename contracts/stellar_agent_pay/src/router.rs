@@ -0,0 +1,99 @@
+use admin_sep::AdministratableExtension;
+use soroban_sdk::{contractclient, contractimpl, contracttype, Address, Env};
+
+use crate::{
+    DataKey, Error, StellarAgentPayContract, StellarAgentPayContractArgs,
+    StellarAgentPayContractClient,
+};
+
+/// Mirror of `multi_asset_router::SwapExactOutResult`
+#[contracttype(export = false)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwapExactOutResult {
+    pub amount_in: i128,
+    pub price_impact: i128,
+    pub fees_paid: i128,
+}
+
+/// The subset of the `multi_asset_router` interface used to pay invoices in other assets
+#[contractclient(name = "RouterClient")]
+pub trait Router {
+    fn get_swap_quote_exact_out(
+        env: Env,
+        from_asset: Address,
+        to_asset: Address,
+        amount_out: i128,
+    ) -> SwapExactOutResult;
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_swap_exact_out(
+        env: Env,
+        from_asset: Address,
+        to_asset: Address,
+        amount_out: i128,
+        max_amount_in: i128,
+        payer: Address,
+        recipient: Address,
+        deadline: u64,
+    ) -> SwapExactOutResult;
+}
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// Set the multi-asset router used by `process_payment_with_asset` (admin only)
+    pub fn set_router(env: Env, router: Address) {
+        Self::require_admin(&env);
+        env.storage().instance().set(&DataKey::Router, &router);
+    }
+
+    /// Get the multi-asset router, if one has been set
+    pub fn get_router(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Router)
+    }
+
+    /// Pay an invoice with `pay_asset`, spending up to `max_amount_in` of it.
+    ///
+    /// The router swaps exactly the invoice amount into the invoice currency, taking
+    /// only as much of `pay_asset` as that needs. Fails with `MaxAmountInExceeded` if
+    /// the router quotes more than `max_amount_in` for it.
+    pub fn process_payment_with_asset(
+        env: Env,
+        invoice_id: u64,
+        payer: Address,
+        pay_asset: Address,
+        max_amount_in: i128,
+    ) -> Result<bool, Error> {
+        payer.require_auth();
+
+        let invoice = Self::payable_invoice(&env, invoice_id)?;
+        if pay_asset == invoice.currency {
+            if max_amount_in < invoice.amount {
+                return Err(Error::MaxAmountInExceeded);
+            }
+            Self::settle(&env, invoice, payer.clone(), &payer);
+            return Ok(true);
+        }
+
+        let router = Self::get_router(env.clone()).ok_or(Error::RouterNotSet)?;
+        let router = RouterClient::new(&env, &router);
+        let quote = router.get_swap_quote_exact_out(&pay_asset, &invoice.currency, &invoice.amount);
+        if quote.amount_in > max_amount_in {
+            return Err(Error::MaxAmountInExceeded);
+        }
+
+        let contract_address = env.current_contract_address();
+        router.execute_swap_exact_out(
+            &pay_asset,
+            &invoice.currency,
+            &invoice.amount,
+            &max_amount_in,
+            &payer,
+            &contract_address,
+            &env.ledger().timestamp(),
+        );
+
+        Self::settle(&env, invoice, payer, &contract_address);
+
+        Ok(true)
+    }
+}
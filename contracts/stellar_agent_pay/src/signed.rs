@@ -0,0 +1,95 @@
+use soroban_sdk::{contractimpl, contracttype, xdr::ToXdr, Address, BytesN, Env, String};
+
+use crate::{
+    storage, DataKey, Error, InvoiceExpiry, StellarAgentPayContract, StellarAgentPayContractArgs,
+    StellarAgentPayContractClient,
+};
+
+/// Invoice terms signed off-chain by the recipient's registered signing key
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvoiceTerms {
+    pub amount: i128,
+    pub currency: Address,
+    pub recipient: Address,
+    pub description: String,
+    /// Unique per recipient; each signed invoice can be paid once
+    pub nonce: u64,
+    /// The terms can no longer be paid from this timestamp
+    pub expires_at: Option<u64>,
+}
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// Register the ed25519 public key `merchant` signs invoice terms with,
+    /// replacing any previous key
+    pub fn set_signing_key(env: Env, merchant: Address, public_key: BytesN<32>) {
+        merchant.require_auth();
+        storage::save(&env, &DataKey::SigningKey(merchant), &public_key);
+    }
+
+    /// Get the ed25519 public key registered by `merchant`
+    pub fn get_signing_key(env: Env, merchant: Address) -> Option<BytesN<32>> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::SigningKey(merchant))
+    }
+
+    /// Whether the signed invoice with `nonce` from `recipient` has been paid
+    pub fn is_nonce_used(env: Env, recipient: Address, nonce: u64) -> bool {
+        env.storage()
+            .persistent()
+            .has(&DataKey::InvoiceNonce(recipient, nonce))
+    }
+
+    /// Create and pay an invoice from terms signed by the recipient, in one call.
+    ///
+    /// `signature` is the recipient's ed25519 signature over the XDR encoding of
+    /// `(contract address, terms)`, checked against the key set with
+    /// `set_signing_key`. An invalid signature aborts the call. Returns the new
+    /// invoice ID.
+    pub fn pay_signed_invoice(
+        env: Env,
+        terms: InvoiceTerms,
+        signature: BytesN<64>,
+        payer: Address,
+    ) -> Result<u64, Error> {
+        payer.require_auth();
+
+        let public_key = Self::get_signing_key(env.clone(), terms.recipient.clone())
+            .ok_or(Error::SigningKeyNotSet)?;
+        if Self::is_nonce_used(env.clone(), terms.recipient.clone(), terms.nonce) {
+            return Err(Error::NonceAlreadyUsed);
+        }
+        if terms
+            .expires_at
+            .is_some_and(|expires_at| env.ledger().timestamp() >= expires_at)
+        {
+            return Err(Error::InvoiceExpired);
+        }
+
+        let message = (env.current_contract_address(), terms.clone()).to_xdr(&env);
+        env.crypto()
+            .ed25519_verify(&public_key, &message, &signature);
+
+        storage::save(
+            &env,
+            &DataKey::InvoiceNonce(terms.recipient.clone(), terms.nonce),
+            &(),
+        );
+
+        let invoice_id = Self::new_invoice(
+            &env,
+            terms.amount,
+            terms.currency,
+            terms.recipient,
+            terms.description,
+            terms.expires_at.map(InvoiceExpiry::At),
+            None,
+        )?;
+        let invoice = Self::payable_invoice(&env, invoice_id)?;
+        Self::settle(&env, invoice, payer.clone(), &payer);
+
+        Ok(invoice_id)
+    }
+}
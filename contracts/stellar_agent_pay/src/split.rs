@@ -0,0 +1,206 @@
+use soroban_sdk::{contractimpl, contracttype, token, vec, Address, Env, String, Vec};
+
+use crate::{
+    fees::BPS_DENOMINATOR, storage, DataKey, Error, Invoice, InvoiceExpiry,
+    StellarAgentPayContract, StellarAgentPayContractArgs, StellarAgentPayContractClient,
+};
+
+/// Maximum number of shares on a split invoice
+pub const MAX_SHARES: u32 = 10;
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Share {
+    pub recipient: Address,
+    /// Part of the invoice paid to `recipient`, out of 10 000
+    pub bps: u32,
+}
+
+/// Split `amount` in proportion to `weights` with the largest remainder method.
+///
+/// Every part is its exact proportional amount rounded down or up, the parts add
+/// up to `amount`, and no part exceeds its weight when `amount` is at most the
+/// total weight, so no dust is created or lost. Zero weights get nothing.
+pub fn allocate(env: &Env, amount: i128, weights: &Vec<i128>) -> Vec<i128> {
+    let total: i128 = weights.iter().sum();
+    let mut parts = Vec::new(env);
+    if total == 0 {
+        for _ in weights.iter() {
+            parts.push_back(0);
+        }
+        return parts;
+    }
+
+    let mut remainders = Vec::new(env);
+    let mut leftover = amount;
+    for weight in weights.iter() {
+        let part = amount * weight / total;
+        parts.push_back(part);
+        remainders.push_back(amount * weight % total);
+        leftover -= part;
+    }
+
+    // Each unit left over goes to a different part, largest remainder first
+    for _ in 0..leftover {
+        let mut best = 0;
+        for (index, remainder) in remainders.iter().enumerate() {
+            if remainder > remainders.get_unchecked(best) {
+                best = index as u32;
+            }
+        }
+        parts.set(best, parts.get_unchecked(best) + 1);
+        remainders.set(best, -1);
+    }
+    parts
+}
+
+#[contractimpl]
+impl StellarAgentPayContract {
+    /// Create an invoice whose payment is split between `shares`, which must add
+    /// up to 10 000 bps. `recipient` owns the invoice and may refund it.
+    pub fn create_split_invoice(
+        env: Env,
+        amount: i128,
+        currency: Address,
+        recipient: Address,
+        description: String,
+        expiry: Option<InvoiceExpiry>,
+        shares: Vec<Share>,
+    ) -> Result<u64, Error> {
+        if shares.is_empty() || shares.len() > MAX_SHARES {
+            return Err(Error::InvalidShares);
+        }
+        let mut total_bps = 0;
+        for share in shares.iter() {
+            if share.bps == 0 {
+                return Err(Error::InvalidShares);
+            }
+            total_bps += share.bps;
+        }
+        if total_bps != BPS_DENOMINATOR {
+            return Err(Error::InvalidShares);
+        }
+
+        let invoice_id =
+            Self::new_invoice(&env, amount, currency, recipient, description, expiry, None)?;
+        storage::save(&env, &DataKey::Shares(invoice_id), &shares);
+        Ok(invoice_id)
+    }
+
+    /// Get the shares an invoice is paid out to. Invoices that are not split
+    /// have a single full share for their recipient.
+    pub fn get_invoice_shares(env: Env, invoice_id: u64) -> Result<Vec<Share>, Error> {
+        let invoice = storage::load_invoice(&env, invoice_id)?;
+        Ok(Self::shares(&env, &invoice))
+    }
+
+    /// Get how much of a paid invoice each share still holds, or is owed while
+    /// the payment is in escrow, net of the platform fee and refunds
+    pub fn get_share_balances(env: Env, invoice_id: u64) -> Result<Vec<i128>, Error> {
+        let invoice = storage::load_invoice(&env, invoice_id)?;
+        Ok(Self::share_balances(&env, &invoice))
+    }
+}
+
+impl StellarAgentPayContract {
+    fn shares(env: &Env, invoice: &Invoice) -> Vec<Share> {
+        let key = DataKey::Shares(invoice.id);
+        match env.storage().persistent().get(&key) {
+            Some(shares) => {
+                storage::bump(env, &key);
+                shares
+            }
+            None => vec![
+                env,
+                Share {
+                    recipient: invoice.recipient.clone(),
+                    bps: BPS_DENOMINATOR,
+                },
+            ],
+        }
+    }
+
+    fn share_balances(env: &Env, invoice: &Invoice) -> Vec<i128> {
+        let key = DataKey::ShareBalances(invoice.id);
+        match env.storage().persistent().get(&key) {
+            Some(balances) => {
+                storage::bump(env, &key);
+                balances
+            }
+            None => {
+                let refunded =
+                    invoice.refunded_total - invoice.fee_refunded(invoice.refunded_total);
+                vec![env, invoice.amount - invoice.fee_amount - refunded]
+            }
+        }
+    }
+
+    /// Split the recipients' side of a newly paid invoice between its shares,
+    /// transferring each part out of `source` unless the payment is held in escrow
+    pub(crate) fn pay_shares(env: &Env, invoice: &Invoice, source: &Address, held: bool) {
+        let shares = Self::shares(env, invoice);
+        let mut weights = Vec::new(env);
+        for share in shares.iter() {
+            weights.push_back(i128::from(share.bps));
+        }
+        let balances = allocate(env, invoice.amount - invoice.fee_amount, &weights);
+        if shares.len() > 1 {
+            storage::save(env, &DataKey::ShareBalances(invoice.id), &balances);
+        }
+        if !held {
+            Self::transfer_shares(env, invoice, &shares, &balances, source);
+        }
+    }
+
+    /// Pay out the shares' balances of an escrowed invoice from the contract
+    pub(crate) fn release_shares(env: &Env, invoice: &Invoice) {
+        let shares = Self::shares(env, invoice);
+        let balances = Self::share_balances(env, invoice);
+        Self::transfer_shares(
+            env,
+            invoice,
+            &shares,
+            &balances,
+            &env.current_contract_address(),
+        );
+    }
+
+    /// Take `amount` off the shares' balances in proportion to them, returning
+    /// each share's recipient and part
+    pub(crate) fn refund_shares(
+        env: &Env,
+        invoice: &Invoice,
+        amount: i128,
+    ) -> Vec<(Address, i128)> {
+        let shares = Self::shares(env, invoice);
+        let mut balances = Self::share_balances(env, invoice);
+        let parts = allocate(env, amount, &balances);
+
+        let mut refunds = Vec::new(env);
+        for (index, share) in shares.iter().enumerate() {
+            let index = index as u32;
+            let part = parts.get_unchecked(index);
+            balances.set(index, balances.get_unchecked(index) - part);
+            refunds.push_back((share.recipient, part));
+        }
+        if shares.len() > 1 {
+            storage::save(env, &DataKey::ShareBalances(invoice.id), &balances);
+        }
+        refunds
+    }
+
+    fn transfer_shares(
+        env: &Env,
+        invoice: &Invoice,
+        shares: &Vec<Share>,
+        amounts: &Vec<i128>,
+        source: &Address,
+    ) {
+        let token_client = token::Client::new(env, &invoice.currency);
+        for (share, amount) in shares.iter().zip(amounts.iter()) {
+            if amount > 0 {
+                token_client.transfer(source, &share.recipient, &amount);
+            }
+        }
+    }
+}
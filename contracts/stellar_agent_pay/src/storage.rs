@@ -0,0 +1,72 @@
+//! Persistent storage access that keeps entries alive.
+//!
+//! Every read and write of an invoice or recurring payment extends the entry's TTL
+//! to `MAX_BUMP`, so anything still in use is never archived.
+
+use soroban_sdk::{Env, IntoVal, Val, Vec};
+
+use crate::{DataKey, Error, Escrow, Invoice, RecurringPayment, RefundRecord};
+
+pub const MAX_BUMP: u32 = 535_679;
+
+/// Extend the TTL of the contract instance, which holds the counters and settings
+pub(crate) fn bump_instance(env: &Env) {
+    env.storage().instance().extend_ttl(MAX_BUMP, MAX_BUMP);
+}
+
+/// Extend the TTL of a persistent entry, if it exists
+pub(crate) fn bump(env: &Env, key: &DataKey) {
+    let storage = env.storage().persistent();
+    if storage.has(key) {
+        storage.extend_ttl(key, MAX_BUMP, MAX_BUMP);
+    }
+}
+
+/// Write a persistent entry with the maximum TTL
+pub(crate) fn save<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    let storage = env.storage().persistent();
+    storage.set(key, value);
+    storage.extend_ttl(key, MAX_BUMP, MAX_BUMP);
+}
+
+pub(crate) fn load_invoice(env: &Env, invoice_id: u64) -> Result<Invoice, Error> {
+    let key = DataKey::Invoice(invoice_id);
+    let invoice = env
+        .storage()
+        .persistent()
+        .get(&key)
+        .ok_or(Error::InvoiceNotFound)?;
+    env.storage()
+        .persistent()
+        .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+    Ok(invoice)
+}
+
+pub(crate) fn save_invoice(env: &Env, invoice: &Invoice) {
+    save(env, &DataKey::Invoice(invoice.id), invoice);
+}
+
+pub(crate) fn save_escrow(env: &Env, invoice_id: u64, escrow: &Escrow) {
+    save(env, &DataKey::Escrow(invoice_id), escrow);
+}
+
+pub(crate) fn save_refunds(env: &Env, invoice_id: u64, refunds: &Vec<RefundRecord>) {
+    save(env, &DataKey::Refunds(invoice_id), refunds);
+}
+
+pub(crate) fn load_recurring(env: &Env, recurring_id: u64) -> Result<RecurringPayment, Error> {
+    let key = DataKey::RecurringPayment(recurring_id);
+    let recurring = env
+        .storage()
+        .persistent()
+        .get(&key)
+        .ok_or(Error::InvoiceNotFound)?;
+    env.storage()
+        .persistent()
+        .extend_ttl(&key, MAX_BUMP, MAX_BUMP);
+    Ok(recurring)
+}
+
+pub(crate) fn save_recurring(env: &Env, recurring: &RecurringPayment) {
+    save(env, &DataKey::RecurringPayment(recurring.id), recurring);
+}
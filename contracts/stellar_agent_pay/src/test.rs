@@ -0,0 +1,1598 @@
+extern crate std;
+
+use ed25519_dalek::{Signer, SigningKey};
+
+use crate::{
+    events, router::SwapExactOutResult, storage::MAX_BUMP, DataKey, Error, EscrowStatus,
+    InvoiceExpiry, InvoiceItems, InvoiceStatus, InvoiceTerms, LineItem, RecurringStatus,
+    RefundRecord, Share, StellarAgentPayContract, StellarAgentPayContractClient,
+};
+use soroban_sdk::{
+    contract, contractimpl,
+    testutils::{
+        storage::{Instance as _, Persistent as _},
+        Address as _, Events as _, Ledger,
+    },
+    token::{StellarAssetClient, TokenClient},
+    vec,
+    xdr::ToXdr,
+    Address, BytesN, Env, Event, String,
+};
+
+/// Swaps at a fixed rate of two output tokens per input token from its own balance
+#[contract]
+pub struct MockRouter;
+
+#[contractimpl]
+impl MockRouter {
+    pub fn get_swap_quote_exact_out(
+        _env: Env,
+        _from_asset: Address,
+        _to_asset: Address,
+        amount_out: i128,
+    ) -> SwapExactOutResult {
+        SwapExactOutResult {
+            amount_in: (amount_out + 1) / 2,
+            price_impact: 0,
+            fees_paid: 0,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_swap_exact_out(
+        env: Env,
+        from_asset: Address,
+        to_asset: Address,
+        amount_out: i128,
+        max_amount_in: i128,
+        payer: Address,
+        recipient: Address,
+        deadline: u64,
+    ) -> SwapExactOutResult {
+        payer.require_auth();
+        assert!(env.ledger().timestamp() <= deadline);
+        let result = Self::get_swap_quote_exact_out(
+            env.clone(),
+            from_asset.clone(),
+            to_asset.clone(),
+            amount_out,
+        );
+        assert!(result.amount_in <= max_amount_in);
+
+        let router = env.current_contract_address();
+        TokenClient::new(&env, &from_asset).transfer(&payer, &router, &result.amount_in);
+        TokenClient::new(&env, &to_asset).transfer(&router, &recipient, &amount_out);
+        result
+    }
+}
+
+struct Setup<'a> {
+    env: Env,
+    client: StellarAgentPayContractClient<'a>,
+    admin: Address,
+    token: TokenClient<'a>,
+    merchant: Address,
+    payer: Address,
+}
+
+impl<'a> Setup<'a> {
+    fn new() -> Self {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger().set_timestamp(1_000);
+
+        let admin = Address::generate(&env);
+        let client = StellarAgentPayContractClient::new(
+            &env,
+            &env.register(StellarAgentPayContract, (admin.clone(),)),
+        );
+
+        let token_admin = Address::generate(&env);
+        let token_address = env
+            .register_stellar_asset_contract_v2(token_admin)
+            .address();
+        let token = TokenClient::new(&env, &token_address);
+
+        let merchant = Address::generate(&env);
+        let payer = Address::generate(&env);
+        StellarAssetClient::new(&env, &token_address).mint(&payer, &10_000);
+
+        Setup {
+            env,
+            client,
+            admin,
+            token,
+            merchant,
+            payer,
+        }
+    }
+
+    fn create_invoice(&self, amount: i128, expiry: Option<InvoiceExpiry>) -> u64 {
+        self.client.create_invoice(
+            &amount,
+            &self.token.address,
+            &self.merchant,
+            &String::from_str(&self.env, "order"),
+            &expiry,
+        )
+    }
+
+    fn paid_invoice(&self, amount: i128) -> u64 {
+        let id = self.create_invoice(amount, None);
+        self.client.process_payment(&id, &self.payer);
+        id
+    }
+
+    fn set_refund_policy(&self, auto_refund_enabled: bool, refund_window_hours: u32) {
+        self.client.update_merchant_preferences(
+            &self.merchant,
+            &self.token.address,
+            &auto_refund_enabled,
+            &refund_window_hours,
+        );
+    }
+
+    /// Let the contract pull refunds that the merchant did not initiate
+    fn approve_refunds(&self, amount: i128) {
+        let expiration_ledger = self.env.ledger().sequence() + 1_000;
+        self.token.approve(
+            &self.merchant,
+            &self.client.address,
+            &amount,
+            &expiration_ledger,
+        );
+    }
+
+    /// Charge `fee_bps` on settled invoices and let the contract pull fee refunds
+    /// from the returned fee recipient
+    fn set_fee(&self, fee_bps: u32) -> Address {
+        let fee_recipient = Address::generate(&self.env);
+        self.client.set_fee_config(&fee_bps, &fee_recipient);
+        let expiration_ledger = self.env.ledger().sequence() + 1_000;
+        self.token.approve(
+            &fee_recipient,
+            &self.client.address,
+            &1_000,
+            &expiration_ledger,
+        );
+        fee_recipient
+    }
+
+    fn refund(&self, id: u64, amount: i128, initiator: &Address) -> Result<bool, Error> {
+        let reason = String::from_str(&self.env, "refund");
+        match self
+            .client
+            .try_execute_refund(&id, &amount, &reason, initiator)
+        {
+            Ok(result) => Ok(result.unwrap()),
+            Err(error) => Err(error.unwrap()),
+        }
+    }
+
+    fn escrow_invoice(&self, amount: i128, arbiter: &Address) -> u64 {
+        self.client.create_escrow_invoice(
+            &amount,
+            &self.token.address,
+            &self.merchant,
+            &String::from_str(&self.env, "order"),
+            &None,
+            arbiter,
+            &3_600,
+        )
+    }
+
+    fn escrow_status(&self, id: u64) -> EscrowStatus {
+        self.client.get_escrow(&id).status
+    }
+
+    /// Assert the events this contract emitted during the last invocation
+    fn assert_events(&self, expected: &[&dyn Event]) {
+        let expected: std::vec::Vec<_> = expected
+            .iter()
+            .map(|event| event.to_xdr(&self.env, &self.client.address))
+            .collect();
+        assert_eq!(
+            self.env
+                .events()
+                .all()
+                .filter_by_contract(&self.client.address),
+            expected
+        );
+    }
+
+    fn schedule_recurring(&self, amount: i128, interval_days: u32, allowance_periods: u32) -> u64 {
+        self.client.schedule_recurring(
+            &amount,
+            &self.token.address,
+            &self.merchant,
+            &interval_days,
+            &self.payer,
+            &allowance_periods,
+            &None,
+            &None,
+        )
+    }
+
+    fn new_token(&self) -> TokenClient<'a> {
+        let address = self
+            .env
+            .register_stellar_asset_contract_v2(Address::generate(&self.env))
+            .address();
+        TokenClient::new(&self.env, &address)
+    }
+
+    fn mint(&self, token: &TokenClient, to: &Address, amount: i128) {
+        StellarAssetClient::new(&self.env, &token.address).mint(to, &amount);
+    }
+
+    fn set_timestamp(&self, timestamp: u64) {
+        self.env.ledger().set_timestamp(timestamp);
+    }
+
+    fn terms(&self, amount: i128, nonce: u64, expires_at: Option<u64>) -> InvoiceTerms {
+        InvoiceTerms {
+            amount,
+            currency: self.token.address.clone(),
+            recipient: self.merchant.clone(),
+            description: String::from_str(&self.env, "order"),
+            nonce,
+            expires_at,
+        }
+    }
+
+    fn sign(&self, key: &SigningKey, terms: &InvoiceTerms) -> BytesN<64> {
+        let message: std::vec::Vec<u8> = (self.client.address.clone(), terms.clone())
+            .to_xdr(&self.env)
+            .iter()
+            .collect();
+        BytesN::from_array(&self.env, &key.sign(&message).to_bytes())
+    }
+
+    fn split_invoice(&self, amount: i128, shares: &[(&Address, u32)]) -> Result<u64, Error> {
+        let mut split = vec![&self.env];
+        for (recipient, bps) in shares {
+            split.push_back(Share {
+                recipient: (*recipient).clone(),
+                bps: *bps,
+            });
+        }
+        match self.client.try_create_split_invoice(
+            &amount,
+            &self.token.address,
+            &self.merchant,
+            &String::from_str(&self.env, "order"),
+            &None,
+            &split,
+        ) {
+            Ok(id) => Ok(id.unwrap()),
+            Err(error) => Err(error.unwrap()),
+        }
+    }
+
+    fn advance_ledgers(&self, ledgers: u32) {
+        self.env
+            .ledger()
+            .with_mut(|ledger| ledger.sequence_number += ledgers);
+    }
+
+    fn ttl(&self, key: &DataKey) -> u32 {
+        self.env.as_contract(&self.client.address, || {
+            self.env.storage().persistent().get_ttl(key)
+        })
+    }
+
+    fn instance_ttl(&self) -> u32 {
+        self.env.as_contract(&self.client.address, || {
+            self.env.storage().instance().get_ttl()
+        })
+    }
+}
+
+#[test]
+fn pay_invoice() {
+    let setup = Setup::new();
+    let id = setup.create_invoice(100, None);
+
+    assert!(setup.client.process_payment(&id, &setup.payer));
+
+    let invoice = setup.client.get_invoice(&id);
+    assert_eq!(invoice.status, InvoiceStatus::Paid);
+    assert_eq!(invoice.payer, Some(setup.payer.clone()));
+    assert_eq!(setup.token.balance(&setup.merchant), 100);
+    assert_eq!(
+        setup.client.try_process_payment(&id, &setup.payer),
+        Err(Ok(Error::InvoiceAlreadyPaid))
+    );
+}
+
+fn line_item(env: &Env, sku: &str, quantity: u32, unit_price: i128) -> LineItem {
+    LineItem {
+        sku: String::from_str(env, sku),
+        quantity,
+        unit_price,
+    }
+}
+
+#[test]
+fn itemized_invoice_total_is_computed_on_chain() {
+    let setup = Setup::new();
+    let env = &setup.env;
+    let items = vec![
+        env,
+        line_item(env, "WIDGET", 3, 250),
+        line_item(env, "SUPPORT", 1, 1_000),
+    ];
+
+    // 1 750 subtotal, less 150 discount, plus 8.25% tax of 1 600 rounded down
+    let id = setup.client.create_invoice_itemized(
+        &setup.token.address,
+        &setup.merchant,
+        &String::from_str(env, "order"),
+        &items,
+        &825,
+        &150,
+        &None,
+    );
+    assert_eq!(setup.client.get_invoice(&id).amount, 1_732);
+    assert_eq!(
+        setup.client.get_invoice_items(&id),
+        InvoiceItems {
+            items,
+            subtotal: 1_750,
+            discount: 150,
+            tax_bps: 825,
+            tax: 132,
+        }
+    );
+
+    setup.client.process_payment(&id, &setup.payer);
+    assert_eq!(setup.token.balance(&setup.merchant), 1_732);
+}
+
+#[test]
+fn itemized_invoice_rejects_invalid_items() {
+    let setup = Setup::new();
+    let env = &setup.env;
+    let create = |items: soroban_sdk::Vec<LineItem>, tax_bps: u32, discount: i128| {
+        setup.client.try_create_invoice_itemized(
+            &setup.token.address,
+            &setup.merchant,
+            &String::from_str(env, "order"),
+            &items,
+            &tax_bps,
+            &discount,
+            &None,
+        )
+    };
+    let widget = line_item(env, "WIDGET", 2, 100);
+
+    assert_eq!(create(vec![env], 0, 0), Err(Ok(Error::InvalidLineItems)));
+    assert_eq!(
+        create(vec![env, line_item(env, "WIDGET", 0, 100)], 0, 0),
+        Err(Ok(Error::InvalidLineItems))
+    );
+    assert_eq!(
+        create(vec![env, line_item(env, "WIDGET", 1, -100)], 0, 0),
+        Err(Ok(Error::InvalidLineItems))
+    );
+    assert_eq!(
+        create(vec![env, widget.clone()], 10_001, 0),
+        Err(Ok(Error::InvalidLineItems))
+    );
+    assert_eq!(
+        create(vec![env, widget.clone()], 0, 200),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        create(vec![env, widget.clone()], 0, -1),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        create(
+            vec![env, line_item(env, "WIDGET", u32::MAX, i128::MAX)],
+            0,
+            0
+        ),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    let id = setup.create_invoice(100, None);
+    assert_eq!(
+        setup.client.try_get_invoice_items(&id),
+        Err(Ok(Error::NotItemizedInvoice))
+    );
+}
+
+#[test]
+fn pay_signed_invoice_creates_and_settles_once() {
+    let setup = Setup::new();
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = BytesN::from_array(&setup.env, key.verifying_key().as_bytes());
+    setup.client.set_signing_key(&setup.merchant, &public_key);
+    assert_eq!(
+        setup.client.get_signing_key(&setup.merchant),
+        Some(public_key)
+    );
+
+    let terms = setup.terms(100, 1, Some(2_000));
+    let signature = setup.sign(&key, &terms);
+    let id = setup
+        .client
+        .pay_signed_invoice(&terms, &signature, &setup.payer);
+
+    let invoice = setup.client.get_invoice(&id);
+    assert_eq!(invoice.status, InvoiceStatus::Paid);
+    assert_eq!(invoice.expires_at, Some(2_000));
+    assert_eq!(setup.token.balance(&setup.merchant), 100);
+    assert!(setup.client.is_nonce_used(&setup.merchant, &1));
+    assert_eq!(
+        setup
+            .client
+            .try_pay_signed_invoice(&terms, &signature, &setup.payer),
+        Err(Ok(Error::NonceAlreadyUsed))
+    );
+}
+
+#[test]
+fn pay_signed_invoice_rejects_invalid_terms() {
+    let setup = Setup::new();
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let terms = setup.terms(100, 1, Some(2_000));
+    let signature = setup.sign(&key, &terms);
+    assert_eq!(
+        setup
+            .client
+            .try_pay_signed_invoice(&terms, &signature, &setup.payer),
+        Err(Ok(Error::SigningKeyNotSet))
+    );
+
+    let public_key = BytesN::from_array(&setup.env, key.verifying_key().as_bytes());
+    setup.client.set_signing_key(&setup.merchant, &public_key);
+
+    // Tampered terms and signatures from another key fail verification
+    let tampered = setup.terms(1, 1, Some(2_000));
+    assert!(setup
+        .client
+        .try_pay_signed_invoice(&tampered, &signature, &setup.payer)
+        .is_err());
+    let other_key = SigningKey::from_bytes(&[8; 32]);
+    let forged = setup.sign(&other_key, &terms);
+    assert!(setup
+        .client
+        .try_pay_signed_invoice(&terms, &forged, &setup.payer)
+        .is_err());
+
+    setup.set_timestamp(2_000);
+    assert_eq!(
+        setup
+            .client
+            .try_pay_signed_invoice(&terms, &signature, &setup.payer),
+        Err(Ok(Error::InvoiceExpired))
+    );
+    assert!(!setup.client.is_nonce_used(&setup.merchant, &1));
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+}
+
+#[test]
+fn invoice_expiry_is_resolved_at_creation() {
+    let setup = Setup::new();
+
+    let ttl = setup.create_invoice(100, Some(InvoiceExpiry::After(900)));
+    assert_eq!(setup.client.get_invoice(&ttl).expires_at, Some(1_900));
+
+    let absolute = setup.create_invoice(100, Some(InvoiceExpiry::At(5_000)));
+    assert_eq!(setup.client.get_invoice(&absolute).expires_at, Some(5_000));
+
+    for expiry in [InvoiceExpiry::At(1_000), InvoiceExpiry::After(0)] {
+        assert_eq!(
+            setup.client.try_create_invoice(
+                &100,
+                &setup.token.address,
+                &setup.merchant,
+                &String::from_str(&setup.env, "order"),
+                &Some(expiry),
+            ),
+            Err(Ok(Error::InvalidExpiry))
+        );
+    }
+}
+
+#[test]
+fn expired_invoice_cannot_be_paid() {
+    let setup = Setup::new();
+    let id = setup.create_invoice(100, Some(InvoiceExpiry::After(900)));
+
+    setup.set_timestamp(1_900);
+    assert_eq!(
+        setup.client.try_process_payment(&id, &setup.payer),
+        Err(Ok(Error::InvoiceExpired))
+    );
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+}
+
+#[test]
+fn expire_invoice() {
+    let setup = Setup::new();
+    let id = setup.create_invoice(100, Some(InvoiceExpiry::After(900)));
+
+    assert!(!setup.client.expire_invoice(&id));
+    assert_eq!(setup.client.get_invoice(&id).status, InvoiceStatus::Pending);
+
+    setup.set_timestamp(2_000);
+    assert!(setup.client.expire_invoice(&id));
+    assert_eq!(setup.client.get_invoice(&id).status, InvoiceStatus::Expired);
+    assert!(!setup.client.expire_invoice(&id));
+    assert_eq!(
+        setup.client.try_process_payment(&id, &setup.payer),
+        Err(Ok(Error::InvoiceExpired))
+    );
+    assert_eq!(
+        setup.client.try_expire_invoice(&42),
+        Err(Ok(Error::InvoiceNotFound))
+    );
+}
+
+#[test]
+fn expire_invoices_sweeps_only_stale_pending_invoices() {
+    let setup = Setup::new();
+    let stale = setup.create_invoice(100, Some(InvoiceExpiry::After(900)));
+    let paid = setup.create_invoice(100, Some(InvoiceExpiry::After(900)));
+    let fresh = setup.create_invoice(100, Some(InvoiceExpiry::At(10_000)));
+    let open = setup.create_invoice(100, None);
+    setup.client.process_payment(&paid, &setup.payer);
+
+    setup.set_timestamp(5_000);
+    let ids = vec![&setup.env, stale, paid, fresh, open, 42];
+    assert_eq!(setup.client.expire_invoices(&ids), 1);
+
+    assert_eq!(
+        setup.client.get_invoice(&stale).status,
+        InvoiceStatus::Expired
+    );
+    assert_eq!(setup.client.get_invoice(&paid).status, InvoiceStatus::Paid);
+    assert_eq!(
+        setup.client.get_invoice(&fresh).status,
+        InvoiceStatus::Pending
+    );
+    assert_eq!(
+        setup.client.get_invoice(&open).status,
+        InvoiceStatus::Pending
+    );
+}
+
+#[test]
+fn recipient_refunds_within_window() {
+    let setup = Setup::new();
+    setup.set_refund_policy(false, 24);
+    let id = setup.paid_invoice(100);
+
+    setup.set_timestamp(1_000 + 24 * 60 * 60);
+    assert_eq!(setup.refund(id, 100, &setup.merchant), Ok(true));
+    assert_eq!(setup.env.auths()[0].0, setup.merchant);
+
+    assert_eq!(
+        setup.client.get_invoice(&id).status,
+        InvoiceStatus::Refunded
+    );
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+    assert_eq!(setup.token.balance(&setup.merchant), 0);
+}
+
+#[test]
+fn refund_rejected_after_window() {
+    let setup = Setup::new();
+    setup.set_refund_policy(true, 24);
+    setup.approve_refunds(100);
+    let id = setup.paid_invoice(100);
+
+    setup.set_timestamp(1_001 + 24 * 60 * 60);
+    for initiator in [&setup.merchant, &setup.admin, &setup.payer] {
+        assert_eq!(
+            setup.refund(id, 100, initiator),
+            Err(Error::RefundWindowClosed)
+        );
+    }
+    assert_eq!(setup.client.get_invoice(&id).status, InvoiceStatus::Paid);
+}
+
+#[test]
+fn refund_without_merchant_config_has_no_window() {
+    let setup = Setup::new();
+    let id = setup.paid_invoice(100);
+
+    setup.set_timestamp(1_000_000);
+    assert_eq!(setup.refund(id, 100, &setup.merchant), Ok(true));
+}
+
+#[test]
+fn admin_refunds_through_merchant_allowance() {
+    let setup = Setup::new();
+    setup.set_refund_policy(false, 24);
+    setup.approve_refunds(100);
+    let id = setup.paid_invoice(100);
+
+    assert_eq!(setup.refund(id, 100, &setup.admin), Ok(true));
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+    assert_eq!(
+        setup
+            .token
+            .allowance(&setup.merchant, &setup.client.address),
+        0
+    );
+}
+
+#[test]
+fn payer_claims_auto_refund() {
+    let setup = Setup::new();
+    setup.set_refund_policy(true, 1);
+    setup.approve_refunds(100);
+    let id = setup.paid_invoice(100);
+
+    assert_eq!(setup.refund(id, 100, &setup.payer), Ok(true));
+    assert_eq!(setup.env.auths()[0].0, setup.payer);
+    assert_eq!(
+        setup.client.get_invoice(&id).status,
+        InvoiceStatus::Refunded
+    );
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+}
+
+#[test]
+fn payer_cannot_refund_without_auto_refund() {
+    let setup = Setup::new();
+    setup.set_refund_policy(false, 24);
+    setup.approve_refunds(100);
+    let id = setup.paid_invoice(100);
+
+    assert_eq!(
+        setup.refund(id, 100, &setup.payer),
+        Err(Error::Unauthorized)
+    );
+}
+
+#[test]
+fn stranger_cannot_refund() {
+    let setup = Setup::new();
+    setup.set_refund_policy(true, 24);
+    setup.approve_refunds(100);
+    let id = setup.paid_invoice(100);
+
+    let stranger = Address::generate(&setup.env);
+    assert_eq!(setup.refund(id, 100, &stranger), Err(Error::Unauthorized));
+    assert_eq!(setup.client.get_invoice(&id).status, InvoiceStatus::Paid);
+}
+
+#[test]
+fn partial_refunds_are_recorded() {
+    let setup = Setup::new();
+    setup.set_refund_policy(false, 24);
+    setup.approve_refunds(100);
+    let id = setup.paid_invoice(100);
+
+    assert_eq!(setup.refund(id, 15, &setup.merchant), Ok(true));
+    let invoice = setup.client.get_invoice(&id);
+    assert_eq!(invoice.status, InvoiceStatus::PartiallyRefunded);
+    assert_eq!(invoice.refunded_total, 15);
+    assert_eq!(setup.token.balance(&setup.payer), 9_915);
+
+    setup.set_timestamp(2_000);
+    assert_eq!(
+        setup.refund(id, 86, &setup.admin),
+        Err(Error::InvalidAmount)
+    );
+    assert_eq!(setup.refund(id, 0, &setup.admin), Err(Error::InvalidAmount));
+    assert_eq!(setup.refund(id, 85, &setup.admin), Ok(true));
+    let invoice = setup.client.get_invoice(&id);
+    assert_eq!(invoice.status, InvoiceStatus::Refunded);
+    assert_eq!(invoice.refunded_total, 100);
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+
+    assert_eq!(
+        setup.client.get_refunds(&id),
+        vec![
+            &setup.env,
+            RefundRecord {
+                amount: 15,
+                reason: String::from_str(&setup.env, "refund"),
+                timestamp: 1_000,
+                initiator: setup.merchant.clone(),
+            },
+            RefundRecord {
+                amount: 85,
+                reason: String::from_str(&setup.env, "refund"),
+                timestamp: 2_000,
+                initiator: setup.admin.clone(),
+            },
+        ]
+    );
+    assert_eq!(
+        setup.refund(id, 1, &setup.merchant),
+        Err(Error::InvoiceNotFound)
+    );
+}
+
+#[test]
+fn refunds_default_to_empty() {
+    let setup = Setup::new();
+    let id = setup.paid_invoice(100);
+
+    assert!(setup.client.get_refunds(&id).is_empty());
+}
+
+#[test]
+fn escrow_payment_is_held_until_payer_releases() {
+    let setup = Setup::new();
+    let arbiter = Address::generate(&setup.env);
+    let id = setup.escrow_invoice(100, &arbiter);
+    assert_eq!(setup.escrow_status(id), EscrowStatus::Unfunded);
+
+    setup.client.process_payment(&id, &setup.payer);
+    let escrow = setup.client.get_escrow(&id);
+    assert_eq!(escrow.status, EscrowStatus::Held);
+    assert_eq!(escrow.release_at, Some(4_600));
+    assert_eq!(setup.token.balance(&setup.client.address), 100);
+    assert_eq!(setup.token.balance(&setup.merchant), 0);
+
+    let stranger = Address::generate(&setup.env);
+    assert_eq!(
+        setup.client.try_release(&id, &stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        setup.client.try_release(&id, &setup.merchant),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    assert!(setup.client.release(&id, &setup.payer));
+    assert_eq!(setup.escrow_status(id), EscrowStatus::Released);
+    assert_eq!(setup.token.balance(&setup.client.address), 0);
+    assert_eq!(setup.token.balance(&setup.merchant), 100);
+    assert_eq!(
+        setup.client.try_release(&id, &setup.payer),
+        Err(Ok(Error::InvalidEscrowState))
+    );
+}
+
+#[test]
+fn escrow_auto_releases_after_timeout() {
+    let setup = Setup::new();
+    let arbiter = Address::generate(&setup.env);
+    let id = setup.escrow_invoice(100, &arbiter);
+    setup.client.process_payment(&id, &setup.payer);
+
+    setup.set_timestamp(4_600);
+    assert_eq!(
+        setup.client.try_dispute(&id, &setup.payer),
+        Err(Ok(Error::InvalidEscrowState))
+    );
+    assert!(setup.client.release(&id, &setup.merchant));
+    assert_eq!(setup.token.balance(&setup.merchant), 100);
+}
+
+#[test]
+fn arbiter_resolves_dispute() {
+    let setup = Setup::new();
+    let arbiter = Address::generate(&setup.env);
+    let id = setup.escrow_invoice(100, &arbiter);
+    setup.client.process_payment(&id, &setup.payer);
+
+    assert_eq!(
+        setup.client.try_dispute(&id, &setup.merchant),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert!(setup.client.dispute(&id, &setup.payer));
+    assert_eq!(setup.escrow_status(id), EscrowStatus::Disputed);
+
+    // Disputed funds stay frozen, even past the timeout
+    setup.set_timestamp(10_000);
+    assert_eq!(
+        setup.client.try_release(&id, &setup.payer),
+        Err(Ok(Error::InvalidEscrowState))
+    );
+    assert_eq!(
+        setup.refund(id, 100, &setup.merchant),
+        Err(Error::InvalidEscrowState)
+    );
+
+    let reason = String::from_str(&setup.env, "damaged");
+    assert_eq!(
+        setup.client.try_resolve_dispute(&id, &101, &reason),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert!(setup.client.resolve_dispute(&id, &40, &reason));
+    assert_eq!(setup.env.auths()[0].0, arbiter);
+
+    let invoice = setup.client.get_invoice(&id);
+    assert_eq!(setup.escrow_status(id), EscrowStatus::Resolved);
+    assert_eq!(invoice.status, InvoiceStatus::PartiallyRefunded);
+    assert_eq!(invoice.refunded_total, 40);
+    assert_eq!(
+        setup.client.get_refunds(&id).get(0).unwrap().initiator,
+        arbiter
+    );
+    assert_eq!(setup.token.balance(&setup.payer), 9_940);
+    assert_eq!(setup.token.balance(&setup.merchant), 60);
+    assert_eq!(setup.token.balance(&setup.client.address), 0);
+}
+
+#[test]
+fn refund_from_escrow_uses_held_funds() {
+    let setup = Setup::new();
+    let arbiter = Address::generate(&setup.env);
+    let id = setup.escrow_invoice(100, &arbiter);
+    setup.client.process_payment(&id, &setup.payer);
+
+    assert_eq!(setup.refund(id, 30, &setup.admin), Ok(true));
+    assert_eq!(setup.token.balance(&setup.payer), 9_930);
+    assert_eq!(setup.token.balance(&setup.client.address), 70);
+
+    assert!(setup.client.release(&id, &arbiter));
+    assert_eq!(setup.token.balance(&setup.merchant), 70);
+    assert_eq!(setup.token.balance(&setup.client.address), 0);
+}
+
+#[test]
+fn escrow_entry_points_reject_direct_invoices() {
+    let setup = Setup::new();
+    let id = setup.paid_invoice(100);
+
+    assert_eq!(
+        setup.client.try_release(&id, &setup.payer),
+        Err(Ok(Error::NotEscrowInvoice))
+    );
+    assert_eq!(
+        setup.client.try_dispute(&id, &setup.payer),
+        Err(Ok(Error::NotEscrowInvoice))
+    );
+    assert_eq!(
+        setup.client.try_get_escrow(&id),
+        Err(Ok(Error::NotEscrowInvoice))
+    );
+}
+
+#[test]
+fn platform_fee_is_taken_on_payment() {
+    let setup = Setup::new();
+    assert_eq!(setup.client.get_fee_config(), None);
+    let fee_recipient = setup.set_fee(250);
+    assert_eq!(setup.client.get_fee_config().unwrap().fee_bps, 250);
+
+    let id = setup.paid_invoice(1_000);
+    let invoice = setup.client.get_invoice(&id);
+    assert_eq!(invoice.fee_amount, 25);
+    assert_eq!(invoice.fee_recipient, Some(fee_recipient.clone()));
+    assert_eq!(setup.token.balance(&setup.merchant), 975);
+    assert_eq!(setup.token.balance(&fee_recipient), 25);
+    assert_eq!(setup.token.balance(&setup.payer), 9_000);
+
+    assert_eq!(
+        setup.client.try_set_fee_config(&10_001, &fee_recipient),
+        Err(Ok(Error::InvalidFee))
+    );
+}
+
+#[test]
+fn platform_fee_rounds_down_on_small_amounts() {
+    let setup = Setup::new();
+    let fee_recipient = setup.set_fee(250);
+
+    // 2.5% of 39 is 0.975, of 40 exactly 1, of 79 1.975
+    for (amount, fee) in [(1, 0), (39, 0), (40, 1), (79, 1)] {
+        let id = setup.paid_invoice(amount);
+        assert_eq!(setup.client.get_invoice(&id).fee_amount, fee);
+    }
+    assert_eq!(setup.token.balance(&fee_recipient), 2);
+    assert_eq!(setup.token.balance(&setup.merchant), 157);
+}
+
+#[test]
+fn refunds_return_platform_fee_in_proportion() {
+    let setup = Setup::new();
+    let fee_recipient = setup.set_fee(250);
+    let id = setup.paid_invoice(1_000);
+
+    // 2.5% of 333 rounds down to 8; the final refund returns the remaining 17
+    setup.token.transfer(&setup.payer, &setup.merchant, &25);
+    assert_eq!(setup.refund(id, 333, &setup.merchant), Ok(true));
+    assert_eq!(setup.token.balance(&fee_recipient), 17);
+    assert_eq!(setup.token.balance(&setup.merchant), 1_000 - 325);
+
+    assert_eq!(setup.refund(id, 667, &setup.merchant), Ok(true));
+    assert_eq!(setup.token.balance(&fee_recipient), 0);
+    assert_eq!(setup.token.balance(&setup.merchant), 25);
+    assert_eq!(setup.token.balance(&setup.payer), 9_975);
+    assert_eq!(
+        setup.client.get_invoice(&id).status,
+        InvoiceStatus::Refunded
+    );
+}
+
+#[test]
+fn refund_initiator_covers_the_fee_without_an_allowance() {
+    let setup = Setup::new();
+    let fee_recipient = Address::generate(&setup.env);
+    setup.client.set_fee_config(&250, &fee_recipient);
+    let id = setup.paid_invoice(1_000);
+
+    // The merchant refunds its 975 and covers the platform's 25
+    setup.token.transfer(&setup.payer, &setup.merchant, &25);
+    assert_eq!(setup.refund(id, 1_000, &setup.merchant), Ok(true));
+    assert_eq!(setup.token.balance(&fee_recipient), 25);
+    assert_eq!(setup.token.balance(&setup.merchant), 0);
+    assert_eq!(setup.token.balance(&setup.payer), 9_975);
+}
+
+#[test]
+fn escrow_release_pays_platform_fee() {
+    let setup = Setup::new();
+    let fee_recipient = setup.set_fee(1_000);
+    let arbiter = Address::generate(&setup.env);
+    let id = setup.escrow_invoice(100, &arbiter);
+    setup.client.process_payment(&id, &setup.payer);
+    assert_eq!(setup.token.balance(&setup.client.address), 100);
+
+    // 30 refunded from held funds carries 3 of the fee back to the payer
+    assert_eq!(setup.refund(id, 30, &setup.admin), Ok(true));
+    assert!(setup.client.release(&id, &setup.payer));
+    assert_eq!(setup.token.balance(&setup.merchant), 63);
+    assert_eq!(setup.token.balance(&fee_recipient), 7);
+    assert_eq!(setup.token.balance(&setup.client.address), 0);
+}
+
+#[test]
+fn split_invoice_pays_each_share() {
+    let setup = Setup::new();
+    let platform = Address::generate(&setup.env);
+    let affiliate = Address::generate(&setup.env);
+    let shares = [
+        (&setup.merchant, 7_000),
+        (&platform, 2_000),
+        (&affiliate, 1_000),
+    ];
+    let id = setup.split_invoice(1_001, &shares).unwrap();
+    assert_eq!(setup.client.get_invoice_shares(&id).len(), 3);
+
+    // 700.7 / 200.2 / 100.1: the leftover unit goes to the largest remainder
+    setup.client.process_payment(&id, &setup.payer);
+    assert_eq!(setup.token.balance(&setup.merchant), 701);
+    assert_eq!(setup.token.balance(&platform), 200);
+    assert_eq!(setup.token.balance(&affiliate), 100);
+    assert_eq!(
+        setup.client.get_share_balances(&id),
+        vec![&setup.env, 701, 200, 100]
+    );
+}
+
+#[test]
+fn split_invoice_shares_must_cover_the_whole_amount() {
+    let setup = Setup::new();
+    let platform = Address::generate(&setup.env);
+    for shares in [
+        &[][..],
+        &[(&setup.merchant, 7_000), (&platform, 2_999)][..],
+        &[(&setup.merchant, 10_000), (&platform, 0)][..],
+        &[(&setup.merchant, 7_000), (&platform, 3_001)][..],
+        &[(&platform, 1_000); 11][..],
+    ] {
+        assert_eq!(setup.split_invoice(100, shares), Err(Error::InvalidShares));
+    }
+
+    let id = setup.create_invoice(100, None);
+    assert_eq!(
+        setup.client.get_invoice_shares(&id),
+        vec![
+            &setup.env,
+            Share {
+                recipient: setup.merchant.clone(),
+                bps: 10_000,
+            }
+        ]
+    );
+}
+
+#[test]
+fn split_refunds_pull_back_proportionally_without_dust() {
+    let setup = Setup::new();
+    let platform = Address::generate(&setup.env);
+    let affiliate = Address::generate(&setup.env);
+    let shares = [
+        (&setup.merchant, 7_000),
+        (&platform, 2_000),
+        (&affiliate, 1_000),
+    ];
+    let id = setup.split_invoice(1_001, &shares).unwrap();
+    setup.client.process_payment(&id, &setup.payer);
+
+    let expiration_ledger = setup.env.ledger().sequence() + 1_000;
+    for (recipient, _) in shares {
+        setup
+            .token
+            .approve(recipient, &setup.client.address, &1_000, &expiration_ledger);
+    }
+
+    // 233.2 / 66.5 / 33.3 of the 701 / 200 / 100 still held
+    assert_eq!(setup.refund(id, 333, &setup.admin), Ok(true));
+    assert_eq!(
+        setup.client.get_share_balances(&id),
+        vec![&setup.env, 468, 133, 67]
+    );
+    assert_eq!(setup.token.balance(&platform), 133);
+
+    assert_eq!(setup.refund(id, 668, &setup.admin), Ok(true));
+    assert_eq!(
+        setup.client.get_share_balances(&id),
+        vec![&setup.env, 0, 0, 0]
+    );
+    assert_eq!(setup.token.balance(&setup.merchant), 0);
+    assert_eq!(setup.token.balance(&platform), 0);
+    assert_eq!(setup.token.balance(&affiliate), 0);
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+}
+
+#[test]
+fn invoice_lifecycle_events() {
+    let setup = Setup::new();
+    let env = &setup.env;
+    let description = String::from_str(env, "order");
+
+    let id = setup.create_invoice(100, Some(InvoiceExpiry::After(900)));
+    setup.assert_events(&[&events::InvoiceCreated {
+        invoice_id: id,
+        amount: 100,
+        currency: setup.token.address.clone(),
+        recipient: setup.merchant.clone(),
+        description: description.clone(),
+        created_at: 1_000,
+        expires_at: Some(1_900),
+        arbiter: None,
+        release_timeout: None,
+    }]);
+
+    setup.client.process_payment(&id, &setup.payer);
+    setup.assert_events(&[&events::InvoicePaid {
+        invoice_id: id,
+        payer: setup.payer.clone(),
+        amount: 100,
+        currency: setup.token.address.clone(),
+        recipient: setup.merchant.clone(),
+        paid_at: 1_000,
+        fee_amount: 0,
+        release_at: None,
+    }]);
+
+    setup.refund(id, 25, &setup.merchant).unwrap();
+    setup.assert_events(&[&events::InvoiceRefunded {
+        invoice_id: id,
+        payer: setup.payer.clone(),
+        amount: 25,
+        refunded_total: 25,
+        reason: String::from_str(env, "refund"),
+        initiator: setup.merchant.clone(),
+        refunded_at: 1_000,
+    }]);
+
+    let stale = setup.create_invoice(100, Some(InvoiceExpiry::After(900)));
+    setup.set_timestamp(1_900);
+    setup.client.expire_invoices(&vec![env, id, stale]);
+    setup.assert_events(&[&events::InvoiceExpired {
+        invoice_id: stale,
+        recipient: setup.merchant.clone(),
+        expired_at: 1_900,
+    }]);
+}
+
+#[test]
+fn escrow_events() {
+    let setup = Setup::new();
+    let env = &setup.env;
+    let arbiter = Address::generate(env);
+
+    let id = setup.escrow_invoice(100, &arbiter);
+    setup.assert_events(&[&events::InvoiceCreated {
+        invoice_id: id,
+        amount: 100,
+        currency: setup.token.address.clone(),
+        recipient: setup.merchant.clone(),
+        description: String::from_str(env, "order"),
+        created_at: 1_000,
+        expires_at: None,
+        arbiter: Some(arbiter.clone()),
+        release_timeout: Some(3_600),
+    }]);
+
+    setup.client.process_payment(&id, &setup.payer);
+    setup.assert_events(&[&events::InvoicePaid {
+        invoice_id: id,
+        payer: setup.payer.clone(),
+        amount: 100,
+        currency: setup.token.address.clone(),
+        recipient: setup.merchant.clone(),
+        paid_at: 1_000,
+        fee_amount: 0,
+        release_at: Some(4_600),
+    }]);
+
+    setup.client.dispute(&id, &setup.payer);
+    setup.assert_events(&[&events::EscrowDisputed {
+        invoice_id: id,
+        payer: setup.payer.clone(),
+        disputed_at: 1_000,
+    }]);
+
+    let reason = String::from_str(env, "damaged");
+    setup.client.resolve_dispute(&id, &40, &reason);
+    setup.assert_events(&[
+        &events::InvoiceRefunded {
+            invoice_id: id,
+            payer: setup.payer.clone(),
+            amount: 40,
+            refunded_total: 40,
+            reason,
+            initiator: arbiter.clone(),
+            refunded_at: 1_000,
+        },
+        &events::DisputeResolved {
+            invoice_id: id,
+            arbiter,
+            refund_amount: 40,
+            recipient_amount: 60,
+            resolved_at: 1_000,
+        },
+    ]);
+
+    let released = setup.escrow_invoice(100, &Address::generate(env));
+    setup.client.process_payment(&released, &setup.payer);
+    setup.client.release(&released, &setup.payer);
+    setup.assert_events(&[&events::EscrowReleased {
+        invoice_id: released,
+        recipient: setup.merchant.clone(),
+        amount: 100,
+        released_by: setup.payer.clone(),
+        released_at: 1_000,
+    }]);
+}
+
+#[test]
+fn recurring_events() {
+    let setup = Setup::new();
+    let interval = 30 * 24 * 60 * 60;
+
+    let id = setup.schedule_recurring(50, 30, 12);
+    setup.assert_events(&[&events::RecurringScheduled {
+        recurring_id: id,
+        payer: setup.payer.clone(),
+        amount: 50,
+        currency: setup.token.address.clone(),
+        recipient: setup.merchant.clone(),
+        interval_days: 30,
+        next_payment: 1_000 + interval,
+        max_payments: None,
+        end_at: None,
+    }]);
+
+    setup.set_timestamp(1_000 + interval);
+    setup.client.execute_recurring_payment(&id);
+    setup.assert_events(&[&events::RecurringExecuted {
+        recurring_id: id,
+        payer: setup.payer.clone(),
+        amount: 50,
+        currency: setup.token.address.clone(),
+        recipient: setup.merchant.clone(),
+        executed_at: 1_000 + interval,
+        next_payment: 1_000 + 2 * interval,
+        payments_made: 1,
+    }]);
+
+    setup.client.cancel_recurring(&id, &setup.payer);
+    setup.assert_events(&[&events::RecurringCancelled {
+        recurring_id: id,
+        cancelled_by: setup.payer.clone(),
+        cancelled_at: 1_000 + interval,
+    }]);
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[test]
+fn schedule_recurring_approves_allowance() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(50, 30, 12);
+
+    let recurring = setup.client.get_recurring_payment(&id);
+    assert_eq!(recurring.payer, setup.payer);
+    assert_eq!(recurring.next_payment, 1_000 + 30 * DAY);
+    assert_eq!(
+        setup.token.allowance(&setup.payer, &setup.client.address),
+        600
+    );
+
+    // A second subscription adds to the existing allowance
+    setup.schedule_recurring(10, 7, 4);
+    assert_eq!(
+        setup.token.allowance(&setup.payer, &setup.client.address),
+        640
+    );
+
+    assert_eq!(
+        setup.client.try_schedule_recurring(
+            &50,
+            &setup.token.address,
+            &setup.merchant,
+            &0,
+            &setup.payer,
+            &12,
+            &None,
+            &None,
+        ),
+        Err(Ok(Error::InvalidInterval))
+    );
+}
+
+#[test]
+fn keeper_executes_recurring_payment_without_payer_auth() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(50, 30, 12);
+
+    assert!(!setup.client.execute_recurring_payment(&id));
+
+    setup.set_timestamp(1_000 + 30 * DAY);
+    assert!(setup.client.execute_recurring_payment(&id));
+    assert!(setup.env.auths().is_empty());
+    assert_eq!(setup.token.balance(&setup.merchant), 50);
+    assert_eq!(setup.token.balance(&setup.payer), 9_950);
+    assert_eq!(
+        setup.token.allowance(&setup.payer, &setup.client.address),
+        550
+    );
+
+    // Already collected for this period
+    assert!(!setup.client.execute_recurring_payment(&id));
+    assert_eq!(
+        setup.client.get_recurring_payment(&id).next_payment,
+        1_000 + 60 * DAY
+    );
+}
+
+#[test]
+fn recurring_payment_catches_up_missed_periods() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(50, 30, 12);
+
+    // Six periods have elapsed; only the last MAX_CATCH_UP_PERIODS are collected
+    setup.set_timestamp(1_000 + 180 * DAY);
+    let mut collected = 0;
+    while setup.client.execute_recurring_payment(&id) {
+        collected += 1;
+    }
+    assert_eq!(collected, crate::MAX_CATCH_UP_PERIODS);
+    assert_eq!(setup.token.balance(&setup.merchant), 150);
+    assert_eq!(
+        setup.client.get_recurring_payment(&id).next_payment,
+        1_000 + 210 * DAY
+    );
+}
+
+#[test]
+fn recurring_payment_is_limited_by_allowance() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(50, 30, 1);
+
+    setup.set_timestamp(1_000 + 30 * DAY);
+    assert!(setup.client.execute_recurring_payment(&id));
+
+    setup.set_timestamp(1_000 + 60 * DAY);
+    assert!(setup.client.try_execute_recurring_payment(&id).is_err());
+    assert_eq!(setup.token.balance(&setup.merchant), 50);
+}
+
+#[test]
+fn pause_and_resume_recurring() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(50, 30, 12);
+
+    setup.client.pause_recurring(&id, &setup.payer);
+    assert_eq!(
+        setup.client.get_recurring_payment(&id).status,
+        RecurringStatus::Paused
+    );
+    assert_eq!(
+        setup.client.try_pause_recurring(&id, &setup.payer),
+        Err(Ok(Error::InvalidRecurringState))
+    );
+
+    // Nothing is collected while paused
+    setup.set_timestamp(1_000 + 65 * DAY);
+    assert!(!setup.client.execute_recurring_payment(&id));
+
+    // Resuming skips the periods that fell due while paused
+    setup.client.resume_recurring(&id, &setup.payer);
+    let recurring = setup.client.get_recurring_payment(&id);
+    assert_eq!(recurring.status, RecurringStatus::Active);
+    assert_eq!(recurring.next_payment, 1_000 + 90 * DAY);
+    assert!(!setup.client.execute_recurring_payment(&id));
+
+    setup.set_timestamp(1_000 + 90 * DAY);
+    assert!(setup.client.execute_recurring_payment(&id));
+    assert_eq!(setup.client.get_recurring_payment(&id).payments_made, 1);
+    assert_eq!(setup.token.balance(&setup.merchant), 50);
+}
+
+#[test]
+fn recurring_lifecycle_is_owner_checked() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(50, 30, 12);
+    let stranger = Address::generate(&setup.env);
+
+    assert_eq!(
+        setup.client.try_pause_recurring(&id, &stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        setup.client.try_resume_recurring(&id, &stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        setup
+            .client
+            .try_update_recurring_amount(&id, &stranger, &10),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        setup.client.try_cancel_recurring(&id, &stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    setup.client.cancel_recurring(&id, &setup.payer);
+    assert_eq!(
+        setup.client.get_recurring_payment(&id).status,
+        RecurringStatus::Cancelled
+    );
+    assert_eq!(
+        setup.client.try_resume_recurring(&id, &setup.payer),
+        Err(Ok(Error::InvalidRecurringState))
+    );
+}
+
+#[test]
+fn update_recurring_amount_needs_recipient_consent() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(50, 30, 12);
+
+    setup.client.update_recurring_amount(&id, &setup.payer, &40);
+    let signers: std::vec::Vec<_> = setup
+        .env
+        .auths()
+        .into_iter()
+        .map(|(address, _)| address)
+        .collect();
+    assert_eq!(signers, [setup.payer.clone(), setup.merchant.clone()]);
+    assert_eq!(setup.client.get_recurring_payment(&id).amount, 40);
+
+    setup.set_timestamp(1_000 + 30 * DAY);
+    setup.client.execute_recurring_payment(&id);
+    assert_eq!(setup.token.balance(&setup.merchant), 40);
+}
+
+#[test]
+fn recurring_completes_after_max_payments() {
+    let setup = Setup::new();
+    let id = setup.client.schedule_recurring(
+        &50,
+        &setup.token.address,
+        &setup.merchant,
+        &30,
+        &setup.payer,
+        &12,
+        &Some(2),
+        &None,
+    );
+
+    setup.set_timestamp(1_000 + 60 * DAY);
+    assert!(setup.client.execute_recurring_payment(&id));
+    assert!(setup.client.execute_recurring_payment(&id));
+    let recurring = setup.client.get_recurring_payment(&id);
+    assert_eq!(recurring.payments_made, 2);
+    assert_eq!(recurring.status, RecurringStatus::Completed);
+
+    setup.set_timestamp(1_000 + 90 * DAY);
+    assert!(!setup.client.execute_recurring_payment(&id));
+    assert_eq!(setup.token.balance(&setup.merchant), 100);
+}
+
+#[test]
+fn recurring_stops_at_end_date() {
+    let setup = Setup::new();
+    let id = setup.client.schedule_recurring(
+        &50,
+        &setup.token.address,
+        &setup.merchant,
+        &30,
+        &setup.payer,
+        &12,
+        &None,
+        &Some(1_000 + 45 * DAY),
+    );
+
+    setup.set_timestamp(1_000 + 30 * DAY);
+    assert!(setup.client.execute_recurring_payment(&id));
+    assert_eq!(
+        setup.client.get_recurring_payment(&id).status,
+        RecurringStatus::Completed
+    );
+
+    setup.set_timestamp(1_000 + 60 * DAY);
+    assert!(!setup.client.execute_recurring_payment(&id));
+    assert_eq!(setup.token.balance(&setup.merchant), 50);
+}
+
+#[test]
+fn pay_invoice_with_other_asset_through_router() {
+    let setup = Setup::new();
+    let env = &setup.env;
+    let xlm = setup.new_token();
+    setup.mint(&xlm, &setup.payer, 1_000);
+
+    let router = env.register(MockRouter, ());
+    setup.mint(&setup.token, &router, 1_000);
+
+    let id = setup.create_invoice(100, None);
+    assert_eq!(
+        setup
+            .client
+            .try_process_payment_with_asset(&id, &setup.payer, &xlm.address, &60),
+        Err(Ok(Error::RouterNotSet))
+    );
+
+    setup.client.set_router(&router);
+    assert_eq!(setup.env.auths()[0].0, setup.admin);
+    assert_eq!(setup.client.get_router(), Some(router.clone()));
+
+    assert_eq!(
+        setup
+            .client
+            .try_process_payment_with_asset(&id, &setup.payer, &xlm.address, &49),
+        Err(Ok(Error::MaxAmountInExceeded))
+    );
+
+    assert!(setup
+        .client
+        .process_payment_with_asset(&id, &setup.payer, &xlm.address, &60));
+
+    let invoice = setup.client.get_invoice(&id);
+    assert_eq!(invoice.status, InvoiceStatus::Paid);
+    assert_eq!(invoice.payer, Some(setup.payer.clone()));
+    assert_eq!(setup.token.balance(&setup.merchant), 100);
+    // Only the 50 XLM needed for the invoice amount is spent, out of up to 60
+    assert_eq!(xlm.balance(&setup.payer), 950);
+    assert_eq!(setup.token.balance(&setup.payer), 10_000);
+    assert_eq!(setup.token.balance(&setup.client.address), 0);
+}
+
+#[test]
+fn pay_with_invoice_currency_skips_router() {
+    let setup = Setup::new();
+    let id = setup.create_invoice(100, None);
+
+    assert_eq!(
+        setup
+            .client
+            .try_process_payment_with_asset(&id, &setup.payer, &setup.token.address, &99),
+        Err(Ok(Error::MaxAmountInExceeded))
+    );
+    assert!(setup
+        .client
+        .process_payment_with_asset(&id, &setup.payer, &setup.token.address, &100));
+    assert_eq!(setup.token.balance(&setup.merchant), 100);
+}
+
+#[test]
+fn list_invoices_by_recipient_and_payer() {
+    let setup = Setup::new();
+    let ids: std::vec::Vec<u64> = (0..5).map(|_| setup.create_invoice(100, None)).collect();
+    setup.client.process_payment(&ids[3], &setup.payer);
+    setup.client.process_payment(&ids[1], &setup.payer);
+
+    let ids_of = |invoices: soroban_sdk::Vec<crate::Invoice>| -> std::vec::Vec<u64> {
+        invoices.iter().map(|invoice| invoice.id).collect()
+    };
+
+    assert_eq!(setup.client.count_invoices_by_recipient(&setup.merchant), 5);
+    assert_eq!(
+        ids_of(
+            setup
+                .client
+                .list_invoices_by_recipient(&setup.merchant, &0, &3, &None)
+        ),
+        ids[0..3]
+    );
+    assert_eq!(
+        ids_of(
+            setup
+                .client
+                .list_invoices_by_recipient(&setup.merchant, &3, &3, &None)
+        ),
+        ids[3..5]
+    );
+    assert_eq!(
+        ids_of(setup.client.list_invoices_by_recipient(
+            &setup.merchant,
+            &0,
+            &10,
+            &Some(InvoiceStatus::Pending)
+        )),
+        [ids[0], ids[2], ids[4]]
+    );
+    assert!(setup
+        .client
+        .list_invoices_by_recipient(&setup.merchant, &10, &10, &None)
+        .is_empty());
+
+    assert_eq!(setup.client.count_invoices_by_payer(&setup.payer), 2);
+    assert_eq!(
+        ids_of(
+            setup
+                .client
+                .list_invoices_by_payer(&setup.payer, &0, &10, &None)
+        ),
+        [ids[3], ids[1]]
+    );
+    assert!(setup
+        .client
+        .list_invoices_by_payer(&setup.merchant, &0, &10, &None)
+        .is_empty());
+}
+
+#[test]
+fn list_invoices_caps_page_size() {
+    let setup = Setup::new();
+    for _ in 0..crate::listing::MAX_PAGE_SIZE + 1 {
+        setup.create_invoice(100, None);
+    }
+
+    let page = setup
+        .client
+        .list_invoices_by_recipient(&setup.merchant, &0, &u32::MAX, &None);
+    assert_eq!(page.len(), crate::listing::MAX_PAGE_SIZE);
+}
+
+#[test]
+fn invoice_ttl_is_extended_on_access() {
+    let setup = Setup::new();
+    let id = setup.create_invoice(100, None);
+    assert_eq!(setup.ttl(&DataKey::Invoice(id)), MAX_BUMP);
+
+    // Far past the default TTL of an entry that was never extended
+    setup.advance_ledgers(100_000);
+    assert_eq!(setup.ttl(&DataKey::Invoice(id)), MAX_BUMP - 100_000);
+    setup.client.process_payment(&id, &setup.payer);
+    assert_eq!(setup.ttl(&DataKey::Invoice(id)), MAX_BUMP);
+
+    setup.advance_ledgers(500_000);
+    setup.client.extend_invoice_ttl(&id);
+    assert_eq!(setup.ttl(&DataKey::Invoice(id)), MAX_BUMP);
+    setup.advance_ledgers(500_000);
+    assert_eq!(setup.client.get_invoice(&id).status, InvoiceStatus::Paid);
+    assert_eq!(
+        setup.client.try_extend_invoice_ttl(&99),
+        Err(Ok(Error::InvoiceNotFound))
+    );
+}
+
+#[test]
+fn recurring_payment_outlives_default_ttl() {
+    let setup = Setup::new();
+    let id = setup.schedule_recurring(100, 30, 12);
+    assert_eq!(setup.ttl(&DataKey::RecurringPayment(id)), MAX_BUMP);
+
+    // Each monthly payment keeps the subscription, and the contract instance
+    // holding the settings it's paid under, alive for the next one
+    for month in 1..=3 {
+        setup.advance_ledgers(450_000);
+        setup.set_timestamp(1_000 + month * 30 * DAY);
+        assert!(setup.client.execute_recurring_payment(&id));
+        assert_eq!(setup.ttl(&DataKey::RecurringPayment(id)), MAX_BUMP);
+        assert_eq!(setup.instance_ttl(), MAX_BUMP);
+    }
+    assert_eq!(setup.token.balance(&setup.merchant), 300);
+}